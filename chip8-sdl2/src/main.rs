@@ -1,16 +1,38 @@
 extern crate chip8;
 extern crate sdl2;
 
-use chip8::{quirks::Quirks, Chip8};
-use log::trace;
-
+use chip8::{
+    analyzer::analyze,
+    audio::{Audio, DEFAULT_SAMPLE_RATE},
+    config::Config,
+    disassembler::{disassemble, Syntax},
+    fault::FaultPolicies,
+    memory::{Memory, MEMORY_64K},
+    movie::Movie,
+    platform::Platform,
+    quirks::Quirks,
+    render::Renderer,
+    rewind::DEFAULT_BUDGET,
+    romdb::RomDatabase,
+    screenshot::{self, DEFAULT_PALETTE},
+    timing::Timing,
+    Chip8,
+};
+use log::{trace, warn};
+
+use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
+use sdl2::pixels::PixelFormatEnum;
 
+use std::collections::HashMap;
 use std::io::Read;
-use std::time::{Duration, Instant};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const WIDTH: usize = 640;
+const HEIGHT: usize = 320;
 
 const DEFAULT_PROGRAM: &[u8] = include_bytes!("../../roms/1-tests/1-chip8-logo.ch8");
 
@@ -21,17 +43,73 @@ pub fn main() {
     let mut program = Vec::from(DEFAULT_PROGRAM);
 
     let mut chip8 = Chip8::new();
+    chip8.enable_rewind(1, DEFAULT_BUDGET);
 
     let mut set_values: Vec<(u16, u8)> = vec![];
     let mut stepping = false;
-    let mut stepping_steps = 0u32;
+    let mut quick_save: Option<Vec<u8>> = None;
+    let mut record: Option<String> = None;
+    let mut movie: Option<Movie> = None;
+    let mut config = Config::default();
+    let mut database: Option<RomDatabase> = None;
+    // Settings given on the command line win over the database
+    let mut configured = false;
+    let mut detect = false;
 
     for arg in args.iter().skip(1) {
         match arg.as_str() {
-            "--chip8" | "--quirks=chip8" => chip8.cpu.quirks = Quirks::chip8(),
-            "--superchip" | "--quirks=superchip" => chip8.cpu.quirks = Quirks::superchip(),
-            "--xochip" | "--quirks=xochip" => chip8.cpu.quirks = Quirks::xochip(),
+            "--chip8" | "--quirks=chip8" => {
+                chip8.cpu.quirks = Quirks::chip8();
+                configured = true;
+            }
+            "--vip" => {
+                chip8.cpu.quirks = Quirks::chip8();
+                chip8.cpu.timing = Timing::Vip;
+                configured = true;
+            }
+            "--timing=vip" => chip8.cpu.timing = Timing::Vip,
+            "--timing=fixed" => chip8.cpu.timing = Timing::Fixed,
+            "--superchip" | "--quirks=superchip" => {
+                chip8.cpu.quirks = Quirks::superchip();
+                configured = true;
+            }
+            "--xochip" | "--quirks=xochip" => {
+                chip8.cpu.quirks = Quirks::xochip();
+                chip8.cpu.memory = Memory::with_size(MEMORY_64K).unwrap();
+                configured = true;
+            }
+            path if arg.starts_with("--config=") => {
+                config =
+                    Config::load(Path::new(&path[9..])).unwrap_or_else(|err| panic!("{}", err));
+                config
+                    .apply(&mut chip8)
+                    .unwrap_or_else(|err| panic!("{}", err));
+                configured = true;
+            }
+            platform if arg.starts_with("--platform=") => {
+                chip8
+                    .set_platform(
+                        &platform[11..]
+                            .parse::<Platform>()
+                            .unwrap_or_else(|err| panic!("{}", err)),
+                    )
+                    .unwrap_or_else(|err| panic!("{}", err));
+                configured = true;
+            }
+            path if arg.starts_with("--database=") => {
+                database = Some(
+                    RomDatabase::load(Path::new(&path[11..]))
+                        .unwrap_or_else(|err| panic!("{}", err)),
+                )
+            }
+            "--detect" => detect = true,
             "--stepping" | "-s" => stepping = true,
+            "--strict" => chip8.cpu.faults = FaultPolicies::strict(),
+            seed if arg.starts_with("--seed=") => chip8.seed(
+                seed[7..]
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Failed to parse seed {}", seed)),
+            ),
 
             set if arg.starts_with("--set=") => {
                 // Parse --set=hex:hex, and apply to chip8
@@ -65,6 +143,13 @@ pub fn main() {
                 }
             }
 
+            path if arg.starts_with("--record=") => record = Some(path[9..].to_string()),
+            path if arg.starts_with("--play=") => {
+                let json = std::fs::read_to_string(&path[7..])
+                    .unwrap_or_else(|_| panic!("Unable to read {}", &path[7..]));
+                movie = Some(Movie::from_json(&json).expect("Invalid movie"));
+            }
+
             filename if filename.ends_with(".ch8") => {
                 program.truncate(0);
                 std::fs::File::open(filename)
@@ -79,11 +164,63 @@ pub fn main() {
         }
     }
 
-    chip8.load_program(&program);
+    // Arrow keys and space stand in for the keys the database suggests
+    let mut key_hints = HashMap::new();
+    if !configured {
+        if let Some(info) = database
+            .as_ref()
+            .and_then(|db| chip8.configure_from(db, &program))
+        {
+            println!("Identified {}", info.title);
+            config = info.config.clone();
+            for (hint, name) in [
+                ("up", "Up"),
+                ("down", "Down"),
+                ("left", "Left"),
+                ("right", "Right"),
+                ("a", "Space"),
+            ] {
+                if let Some(&key) = info.keys.get(hint) {
+                    key_hints.insert(name.to_string(), key);
+                }
+            }
+        } else if detect {
+            let analysis = analyze(&program);
+            println!(
+                "Detected {} ({:.0}% sure)",
+                analysis.platform,
+                analysis.confidence * 100.0
+            );
+            chip8
+                .set_platform(&analysis.platform)
+                .unwrap_or_else(|err| panic!("{}", err));
+            chip8.cpu.quirks = analysis.quirks;
+            if analysis.hires_vip {
+                eprintln!("Warning: written for the 64x64 VIP interpreter, which isn't emulated");
+            }
+        }
+    }
+
+    chip8
+        .load_program(&program)
+        .unwrap_or_else(|err| panic!("{}", err));
     for (addr, val) in set_values {
         chip8.cpu.memory.write(addr, val)
     }
 
+    let mut playing = movie.is_some();
+    if let Some(movie) = movie {
+        chip8
+            .start_playback(movie, &program)
+            .expect("Unable to play movie");
+    } else if record.is_some() {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        chip8.start_recording(&program, seed);
+    }
+
     println!("Chip8 running!");
     println!("  [J] to step through instructions");
     println!("  [K] disables stepping");
@@ -91,9 +228,23 @@ pub fn main() {
 
     let sdl_context = sdl2::init().unwrap();
     let sdl_video = sdl_context.video().unwrap();
+    let sdl_audio = sdl_context.audio().unwrap();
+
+    let mut audio = Audio::new(DEFAULT_SAMPLE_RATE);
+    let audio_queue = sdl_audio
+        .open_queue::<f32, _>(
+            None,
+            &AudioSpecDesired {
+                freq: Some(DEFAULT_SAMPLE_RATE as i32),
+                channels: Some(1),
+                samples: None,
+            },
+        )
+        .unwrap();
+    audio_queue.resume();
 
     let mut canvas = sdl_video
-        .window("rschip8", 640, 320)
+        .window("rschip8", WIDTH as u32, HEIGHT as u32)
         .position_centered()
         .build()
         .unwrap()
@@ -102,12 +253,29 @@ pub fn main() {
         .unwrap();
 
     let black = Color::RGB(0, 0, 0);
-    let white = Color::RGB(255, 255, 255);
+
+    let palette = config.palette.clone().unwrap_or(DEFAULT_PALETTE.to_vec());
+    let renderer = Renderer::new(&palette);
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, WIDTH as u32, HEIGHT as u32)
+        .unwrap();
+    let mut frame = vec![0; WIDTH * HEIGHT * 4];
 
     canvas.set_draw_color(black);
     canvas.clear();
     canvas.present();
 
+    let mut keymap = HashMap::new();
+    for (name, key) in key_hints.into_iter().chain(config.keymap()) {
+        match Keycode::from_name(&name) {
+            Some(keycode) => {
+                keymap.insert(keycode, key);
+            }
+            None => warn!("Unknown key {} in keymap", name),
+        }
+    }
+
     let mut event_pump = sdl_context.event_pump().unwrap();
     let desired_frame_time = Duration::from_secs_f64(1.0 / 60.0);
 
@@ -126,27 +294,16 @@ pub fn main() {
                     keycode: Some(keycode),
                     ..
                 } => match keycode {
-                    Keycode::Num1 => chip8.keydown(0x1),
-                    Keycode::Num2 => chip8.keydown(0x2),
-                    Keycode::Num3 => chip8.keydown(0x3),
-                    Keycode::Num4 => chip8.keydown(0xc),
-                    Keycode::Q => chip8.keydown(0x4),
-                    Keycode::W => chip8.keydown(0x5),
-                    Keycode::E => chip8.keydown(0x6),
-                    Keycode::R => chip8.keydown(0xd),
-                    Keycode::A => chip8.keydown(0x7),
-                    Keycode::S => chip8.keydown(0x8),
-                    Keycode::D => chip8.keydown(0x9),
-                    Keycode::F => chip8.keydown(0xe),
-                    Keycode::Z => chip8.keydown(0xa),
-                    Keycode::X => chip8.keydown(0x0),
-                    Keycode::C => chip8.keydown(0xb),
-                    Keycode::V => chip8.keydown(0xf),
+                    key if keymap.contains_key(&key) => chip8.keydown(keymap[&key]),
 
                     #[cfg(debug_assertions)]
                     Keycode::M => {
                         println!("CPU: {}", chip8.cpu);
-                        println!("");
+                        let pc = chip8.cpu.pc as usize;
+                        for line in disassemble(&chip8.cpu.memory, pc..(pc + 8), Syntax::Cowgod) {
+                            println!("  {}", line);
+                        }
+                        println!();
                     }
 
                     #[cfg(debug_assertions)]
@@ -161,7 +318,7 @@ pub fn main() {
                                 .map(|chunk| {
                                     chunk
                                         .iter()
-                                        .map(|v| if *v { "█" } else { " " })
+                                        .map(|v| if *v != 0 { "█" } else { " " })
                                         .collect::<Vec<&str>>()
                                         .join("")
                                 })
@@ -172,19 +329,42 @@ pub fn main() {
                     }
 
                     Keycode::J => {
-                        if !stepping {
-                            stepping = true;
-                            stepping_steps = 0;
-                        }
-                        stepping_steps += chip8.cpu.step();
-                        if stepping_steps >= (chip8.cpu.clock_speed / 60000) {
-                            chip8.cpu.tick_timers();
-                            stepping_steps -= chip8.cpu.clock_speed / 60000;
+                        stepping = true;
+                        if let Err(fault) = chip8.step() {
+                            println!("{}", fault);
                         }
                     }
                     Keycode::K if stepping => stepping = false,
                     Keycode::L if chip8.halted => chip8.resume(),
 
+                    Keycode::Backspace => {
+                        chip8.step_back();
+                    }
+                    Keycode::PageUp => {
+                        chip8.rewind_seconds(5.0);
+                    }
+
+                    Keycode::F12 => {
+                        let secs = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or(0);
+                        let path = format!("screenshot-{}.png", secs);
+                        match screenshot::save(&chip8.cpu, Path::new(&path), &palette, 8) {
+                            Ok(()) => println!("Saved {}", path),
+                            Err(err) => println!("Unable to save {}: {}", path, err),
+                        }
+                    }
+
+                    Keycode::F5 => quick_save = Some(chip8.save_state()),
+                    Keycode::F9 => {
+                        if let Some(state) = &quick_save {
+                            if let Err(err) = chip8.load_state(state) {
+                                println!("{}", err);
+                            }
+                        }
+                    }
+
                     _ => {}
                 },
 
@@ -192,22 +372,7 @@ pub fn main() {
                     keycode: Some(keycode),
                     ..
                 } => match keycode {
-                    Keycode::Num1 => chip8.keyup(0x1),
-                    Keycode::Num2 => chip8.keyup(0x2),
-                    Keycode::Num3 => chip8.keyup(0x3),
-                    Keycode::Num4 => chip8.keyup(0xc),
-                    Keycode::Q => chip8.keyup(0x4),
-                    Keycode::W => chip8.keyup(0x5),
-                    Keycode::E => chip8.keyup(0x6),
-                    Keycode::R => chip8.keyup(0xd),
-                    Keycode::A => chip8.keyup(0x7),
-                    Keycode::S => chip8.keyup(0x8),
-                    Keycode::D => chip8.keyup(0x9),
-                    Keycode::F => chip8.keyup(0xe),
-                    Keycode::Z => chip8.keyup(0xa),
-                    Keycode::X => chip8.keyup(0x0),
-                    Keycode::C => chip8.keyup(0xb),
-                    Keycode::V => chip8.keyup(0xf),
+                    key if keymap.contains_key(&key) => chip8.keyup(keymap[&key]),
 
                     _ => {}
                 },
@@ -218,32 +383,32 @@ pub fn main() {
 
         let tick_start_time = Instant::now();
         if !stepping {
-            chip8.tick();
+            if let Err(fault) = chip8.tick() {
+                println!("{}", fault);
+            }
+
+            if playing && chip8.playback_finished() {
+                match chip8.verify_playback() {
+                    Ok(()) => println!("Movie finished, checksum matches"),
+                    Err(err) => println!("{}", err),
+                }
+                playing = false;
+            }
         }
         let tick_elapsed = Instant::now() - tick_start_time;
 
-        canvas.set_draw_color(black);
-        canvas.clear();
-
-        canvas.set_draw_color(white);
-        let pixel_width = 640 / chip8.cpu.width;
-        let pixel_height = 320 / chip8.cpu.height;
+        audio_queue
+            .queue_audio(&audio.samples(&chip8.cpu, (DEFAULT_SAMPLE_RATE / 60) as usize))
+            .unwrap();
 
         let blit_start_time = Instant::now();
-        for x in 0..chip8.cpu.width {
-            for y in 0..chip8.cpu.height {
-                if chip8.cpu.vram[chip8.cpu.width * y + x] {
-                    canvas
-                        .fill_rect(Rect::new(
-                            (x * pixel_width) as i32,
-                            (y * pixel_height) as i32,
-                            640 / chip8.cpu.width as u32,
-                            320 / chip8.cpu.height as u32,
-                        ))
-                        .unwrap();
-                }
-            }
+        // Only upload the display when the ROM changed it
+        if chip8.cpu.is_dirty() {
+            renderer.render_into(&chip8.cpu, &mut frame, WIDTH, HEIGHT);
+            texture.update(None, &frame, WIDTH * 4).unwrap();
+            chip8.cpu.clear_dirty();
         }
+        canvas.copy(&texture, None, None).unwrap();
         let blit_elapsed = Instant::now() - blit_start_time;
 
         canvas.present();
@@ -266,4 +431,10 @@ pub fn main() {
             ::std::thread::sleep(desired_frame_time - elapsed);
         }
     }
+
+    if let (Some(path), Some(movie)) = (record, chip8.stop_recording()) {
+        std::fs::write(&path, movie.to_json())
+            .unwrap_or_else(|_| panic!("Unable to write {}", path));
+        println!("Recorded {} frames to {}", movie.frames, path);
+    }
 }
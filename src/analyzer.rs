@@ -0,0 +1,317 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use super::{
+    instruction::{Instruction, OpcodeSet},
+    platform::Platform,
+    quirks::Quirks,
+};
+
+const START: u16 = 0x200;
+
+// Something in a program that hints at the platform it was written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finding {
+    // An instruction added by SCHIP or XO-CHIP
+    Extension { addr: u16, set: OpcodeSet },
+    // $0230, the clear screen of the 64x64 VIP interpreter
+    HiresVip { addr: u16 },
+    // 8XY6 or 8XYE with X != Y, shifts VY into VX without the shifting quirk. CHIP-48
+    // assemblers wrote a shift of VX in place as 8X06, which is taken as wanting the quirk
+    Shift { addr: u16, quirk: bool },
+    // BXNN with X != 0, adds VX rather than V0 with the jumping quirk. Wanting the quirk
+    // when the instruction before it sets VX
+    Jump { addr: u16, quirk: bool },
+    // Not an instruction, probably data reached by a path that never runs
+    Unknown { addr: u16 },
+}
+
+impl Finding {
+    pub fn addr(&self) -> u16 {
+        match *self {
+            Finding::Extension { addr, .. }
+            | Finding::HiresVip { addr }
+            | Finding::Shift { addr, .. }
+            | Finding::Jump { addr, .. }
+            | Finding::Unknown { addr } => addr,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub platform: Platform,
+    // The platform's quirks, with shifting and jumping going the way the code expects
+    pub quirks: Quirks,
+    // Written for the 64x64 display of the hires VIP interpreter, which isn't emulated
+    pub hires_vip: bool,
+    // From 0 to 1, 0.5 when nothing gives the platform away
+    pub confidence: f32,
+    pub findings: Vec<Finding>,
+}
+
+// Guess the platform of a program loaded at $200 from the instructions it uses
+pub fn analyze(program: &[u8]) -> Analysis {
+    let (findings, instructions) = scan(program);
+
+    let set = findings
+        .iter()
+        .filter_map(|finding| match finding {
+            Finding::Extension { set, .. } => Some(*set),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(OpcodeSet::Chip8);
+    let count = |matches: fn(&Finding) -> bool| findings.iter().filter(|f| matches(f)).count();
+    let hires_vip = count(|f| matches!(f, Finding::HiresVip { .. })) > 0;
+
+    let (platform, mut confidence) = match set {
+        OpcodeSet::XoChip | OpcodeSet::SuperChip11 | OpcodeSet::SuperChip10 => {
+            let platform = if set == OpcodeSet::XoChip {
+                Platform::xochip()
+            } else {
+                // SCHIP 1.0 ROMs without scrolling run just as well on 1.1
+                Platform::schip_1_1()
+            };
+
+            // Each instruction from the extension makes a mistake less likely
+            let evidence = count(|f| matches!(f, Finding::Extension { .. }));
+            (platform, 1.0 - 0.5f32.powi(evidence as i32 + 1))
+        }
+        OpcodeSet::Chip8 => {
+            // Code relying on quirks only works on some of the CHIP-8 interpreters
+            let shifts = count(|f| matches!(f, Finding::Shift { .. })) > 0;
+            let jumps = count(|f| matches!(f, Finding::Jump { .. })) > 0;
+
+            let base = if hires_vip { 0.75 } else { 0.5 };
+            let ambiguity = 0.8f32.powi(shifts as i32 + jumps as i32);
+            (Platform::cosmac_vip(), base * ambiguity)
+        }
+    };
+
+    // Data decoded as code means the scan went astray somewhere
+    let unknown = count(|f| matches!(f, Finding::Unknown { .. }));
+    if instructions > 0 {
+        confidence *= (instructions - unknown) as f32 / instructions as f32;
+    }
+
+    // Go with whichever way most of the instructions lean, ties keep the preset
+    let lean = |quirk: &mut bool, votes: Vec<bool>| {
+        let wanted = votes.iter().filter(|&&quirk| quirk).count();
+        match (wanted * 2).cmp(&votes.len()) {
+            Ordering::Greater => *quirk = true,
+            Ordering::Less => *quirk = false,
+            Ordering::Equal => {}
+        }
+    };
+    let mut quirks = platform.quirks.clone();
+    lean(
+        &mut quirks.shifting,
+        findings
+            .iter()
+            .filter_map(|f| match *f {
+                Finding::Shift { quirk, .. } => Some(quirk),
+                _ => None,
+            })
+            .collect(),
+    );
+    lean(
+        &mut quirks.jumping,
+        findings
+            .iter()
+            .filter_map(|f| match *f {
+                Finding::Jump { quirk, .. } => Some(quirk),
+                _ => None,
+            })
+            .collect(),
+    );
+
+    Analysis {
+        platform,
+        quirks,
+        hires_vip,
+        confidence,
+        findings,
+    }
+}
+
+// Follow every path from $200 so data between routines isn't mistaken for code,
+// returning the findings and the number of instructions visited
+fn scan(program: &[u8]) -> (Vec<Finding>, usize) {
+    let word = |addr: u16| -> Option<u16> {
+        let offset = addr.checked_sub(START)? as usize;
+        let bytes = program.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    let mut findings = vec![];
+    let mut seen = HashSet::new();
+    let mut pending = vec![START];
+
+    while let Some(addr) = pending.pop() {
+        if !seen.insert(addr) {
+            continue;
+        }
+        let Some(op) = word(addr) else {
+            seen.remove(&addr);
+            continue;
+        };
+
+        let inst = Instruction::parse(op, word(addr.wrapping_add(2)).unwrap_or(0));
+        let next = addr.wrapping_add(inst.size());
+
+        match inst {
+            Instruction::Unknown(_) => findings.push(Finding::Unknown { addr }),
+            // Parsed as CLS, only the raw opcode tells them apart
+            _ if op == 0x0230 => findings.push(Finding::HiresVip { addr }),
+            Instruction::SHR_Vx_Vy(x, y) | Instruction::SHL_Vx_Vy(x, y) if x != y => {
+                findings.push(Finding::Shift {
+                    addr,
+                    quirk: y == 0,
+                })
+            }
+            Instruction::JP_Vx_addr(x, _) if x != 0 => {
+                let before = word(addr.wrapping_sub(2)).map(|op| Instruction::parse(op, 0));
+                findings.push(Finding::Jump {
+                    addr,
+                    quirk: before.and_then(writes) == Some(x),
+                })
+            }
+            _ if inst.opcode_set() != OpcodeSet::Chip8 => findings.push(Finding::Extension {
+                addr,
+                set: inst.opcode_set(),
+            }),
+            _ => {}
+        }
+
+        match inst {
+            Instruction::JP_addr(target) => pending.push(target),
+            Instruction::CALL_addr(target) => pending.extend([next, target]),
+            // Jump tables usually start at NNN
+            Instruction::JP_Vx_addr(_, base) => pending.push(base),
+            Instruction::RET | Instruction::EXIT | Instruction::Unknown(_) => {}
+
+            Instruction::SE_Vx_kk(_, _)
+            | Instruction::SNE_Vx_kk(_, _)
+            | Instruction::SE_Vx_Vy(_, _)
+            | Instruction::SNE_Vx_Vy(_, _)
+            | Instruction::SKP_Vx(_)
+            | Instruction::SKNP_Vx(_) => {
+                let skipped = word(next).map_or(2, Instruction::size_of);
+                pending.extend([next.wrapping_add(skipped), next]);
+            }
+
+            _ => pending.push(next),
+        }
+    }
+
+    findings.sort_by_key(Finding::addr);
+    (findings, seen.len())
+}
+
+// The register an instruction sets, if any
+fn writes(inst: Instruction) -> Option<u8> {
+    match inst {
+        Instruction::LD_Vx_kk(x, _)
+        | Instruction::ADD_Vx_kk(x, _)
+        | Instruction::RND_Vx_kk(x, _)
+        | Instruction::LD_Vx_Vy(x, _)
+        | Instruction::OR_Vx_Vy(x, _)
+        | Instruction::AND_Vx_Vy(x, _)
+        | Instruction::XOR_Vx_Vy(x, _)
+        | Instruction::ADD_Vx_Vy(x, _)
+        | Instruction::SUB_Vx_Vy(x, _)
+        | Instruction::SHR_Vx_Vy(x, _)
+        | Instruction::SUBN_Vx_Vy(x, _)
+        | Instruction::SHL_Vx_Vy(x, _)
+        | Instruction::LD_Vx_DT(x)
+        | Instruction::LD_Vx_K(x) => Some(x),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_chip8_is_a_guess() {
+        let analysis = analyze(include_bytes!("test_data/2-ibm-logo.ch8"));
+
+        assert_eq!(analysis.platform, Platform::cosmac_vip());
+        assert_eq!(analysis.quirks, Quirks::chip8());
+        assert_eq!(analysis.findings, vec![]);
+        assert_eq!(analysis.confidence, 0.5);
+    }
+
+    #[test]
+    fn extension_opcodes_give_the_platform_away() {
+        // HIRES, SCR, jump to self
+        let schip = analyze(&[0x00, 0xff, 0x00, 0xfb, 0x12, 0x04]);
+        assert_eq!(schip.platform, Platform::schip_1_1());
+        assert_eq!(schip.confidence, 0.875);
+        assert_eq!(
+            schip.findings[1],
+            Finding::Extension {
+                addr: 0x202,
+                set: OpcodeSet::SuperChip11
+            }
+        );
+
+        // LD I, $0300 then PLANE 1
+        let xochip = analyze(&[0xf0, 0x00, 0x03, 0x00, 0xf1, 0x01, 0x12, 0x06]);
+        assert_eq!(xochip.platform, Platform::xochip());
+        assert_eq!(xochip.quirks, Quirks::xochip());
+    }
+
+    #[test]
+    fn data_after_a_jump_is_not_code() {
+        // Jump over a word that would be HIRES
+        let analysis = analyze(&[0x12, 0x04, 0x00, 0xff, 0x12, 0x04]);
+        assert_eq!(analysis.platform, Platform::cosmac_vip());
+        assert_eq!(analysis.findings, vec![]);
+    }
+
+    #[test]
+    fn quirk_dependent_code_is_flagged() {
+        // SHR V0, V1 then BXNN through V2, 0230 at the jump target
+        let analysis = analyze(&[0x80, 0x16, 0xb2, 0x06, 0x00, 0x00, 0x02, 0x30, 0x12, 0x06]);
+
+        assert_eq!(
+            analysis.findings,
+            vec![
+                Finding::Shift {
+                    addr: 0x200,
+                    quirk: false
+                },
+                Finding::Jump {
+                    addr: 0x202,
+                    quirk: false
+                },
+                Finding::HiresVip { addr: 0x206 },
+            ]
+        );
+        assert!(analysis.confidence < 0.75);
+        assert!(analysis.hires_vip);
+        assert_eq!(analysis.quirks, Quirks::chip8());
+    }
+
+    #[test]
+    fn quirk_dependent_code_picks_the_quirks() {
+        // SHR V3 the CHIP-48 way, then set V2 and jump through it
+        let analysis = analyze(&[0x83, 0x06, 0x62, 0x04, 0xb2, 0x08, 0x00, 0x00, 0x12, 0x08]);
+
+        assert_eq!(analysis.platform, Platform::cosmac_vip());
+        assert!(analysis.quirks.shifting);
+        assert!(analysis.quirks.jumping);
+        assert!(!analysis.hires_vip);
+    }
+
+    #[test]
+    fn unknown_opcodes_lower_confidence() {
+        let analysis = analyze(&[0x00, 0xff, 0xff, 0xff]);
+
+        assert_eq!(analysis.findings[1], Finding::Unknown { addr: 0x202 });
+        assert_eq!(analysis.confidence, 0.75 / 2.0);
+    }
+}
@@ -0,0 +1,786 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use super::instruction::Instruction;
+
+pub const ORIGIN: u16 = 0x200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Op(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    column: usize,
+}
+
+enum Operand<'a> {
+    Reg(u8),
+    Name(&'static str),
+    Long(&'a [Spanned]),
+    Expr(&'a [Spanned]),
+}
+
+struct Equ {
+    line: usize,
+    column: usize,
+    name: String,
+    tokens: Vec<Spanned>,
+}
+
+struct Statement {
+    line: usize,
+    column: usize,
+    addr: u16,
+    mnemonic: String,
+    operands: Vec<Vec<Spanned>>,
+}
+
+const OPERATORS: [&str; 17] = [
+    "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")", ",", ":", "[", "]",
+];
+
+const NAMES: [&str; 8] = ["I", "DT", "ST", "K", "F", "B", "HF", "R"];
+
+fn error<T>(line: usize, column: usize, message: impl Into<String>) -> Result<T, AsmError> {
+    Err(AsmError {
+        line,
+        column,
+        message: message.into(),
+    })
+}
+
+fn tokenize(line: usize, source: &str) -> Result<Vec<Spanned>, AsmError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let column = pos + 1;
+
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            pos += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '#' || c == '$' {
+            let start = pos;
+            pos += 1;
+            while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || chars[pos] == '_') {
+                pos += 1;
+            }
+            let word: String = chars[start..pos].iter().collect();
+
+            let number = if let Some(hex) = word.strip_prefix('#').or(word.strip_prefix('$')) {
+                Some(i64::from_str_radix(hex, 16))
+            } else if let Some(hex) = word.strip_prefix("0x") {
+                Some(i64::from_str_radix(hex, 16))
+            } else if let Some(bin) = word.strip_prefix("0b") {
+                Some(i64::from_str_radix(bin, 2))
+            } else if c.is_ascii_digit() {
+                Some(word.parse::<i64>())
+            } else {
+                None
+            };
+
+            tokens.push(Spanned {
+                token: match number {
+                    Some(Ok(n)) => Token::Number(n),
+                    Some(Err(_)) => return error(line, column, format!("Invalid number {}", word)),
+                    None => Token::Ident(word),
+                },
+                column,
+            });
+        } else if let Some(op) = OPERATORS
+            .iter()
+            .find(|op| chars[pos..].starts_with(&op.chars().collect::<Vec<char>>()))
+        {
+            tokens.push(Spanned {
+                token: Token::Op(op),
+                column,
+            });
+            pos += op.len();
+        } else {
+            return error(line, column, format!("Unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Split operands on commas that are not nested in parentheses
+fn split_operands(tokens: &[Spanned]) -> Vec<Vec<Spanned>> {
+    let mut operands = vec![];
+    let mut current = vec![];
+    let mut depth = 0;
+
+    for t in tokens {
+        match t.token {
+            Token::Op("(") => depth += 1,
+            Token::Op(")") => depth -= 1,
+            Token::Op(",") if depth == 0 => {
+                operands.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(t.clone());
+    }
+
+    if !current.is_empty() || !operands.is_empty() {
+        operands.push(current);
+    }
+
+    operands
+}
+
+fn register(name: &str) -> Option<u8> {
+    let digits = name.strip_prefix('V').or(name.strip_prefix('v'))?;
+    if digits.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digits, 16).ok()
+}
+
+fn classify(tokens: &[Spanned]) -> Operand<'_> {
+    match tokens {
+        [Spanned {
+            token: Token::Ident(name),
+            ..
+        }] => {
+            if let Some(x) = register(name) {
+                return Operand::Reg(x);
+            }
+            let upper = name.to_ascii_uppercase();
+            match NAMES.iter().find(|n| **n == upper) {
+                Some(n) => Operand::Name(n),
+                None => Operand::Expr(tokens),
+            }
+        }
+        [Spanned {
+            token: Token::Op("["),
+            ..
+        }, Spanned {
+            token: Token::Ident(name),
+            ..
+        }, Spanned {
+            token: Token::Op("]"),
+            ..
+        }] if name.eq_ignore_ascii_case("I") => Operand::Name("[I]"),
+        [Spanned {
+            token: Token::Ident(name),
+            ..
+        }, rest @ ..]
+            if name.eq_ignore_ascii_case("LONG") =>
+        {
+            Operand::Long(rest)
+        }
+        _ => Operand::Expr(tokens),
+    }
+}
+
+fn is_long(operands: &[Vec<Spanned>]) -> bool {
+    operands
+        .iter()
+        .any(|o| matches!(classify(o), Operand::Long(_)))
+}
+
+struct Expr<'a> {
+    tokens: &'a [Spanned],
+    pos: usize,
+    line: usize,
+    symbols: &'a HashMap<String, i64>,
+}
+
+impl<'a> Expr<'a> {
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map(|t| t.column)
+            .unwrap_or(1)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, AsmError> {
+        const LEVELS: [&[&str]; 6] = [
+            &["|"],
+            &["^"],
+            &["&"],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            let column = self.column();
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+
+            let value = match op {
+                "|" => Some(lhs | rhs),
+                "^" => Some(lhs ^ rhs),
+                "&" => Some(lhs & rhs),
+                "<<" => Some(lhs << (rhs & 63)),
+                ">>" => Some(lhs >> (rhs & 63)),
+                "+" => lhs.checked_add(rhs),
+                "-" => lhs.checked_sub(rhs),
+                "*" => lhs.checked_mul(rhs),
+                _ if rhs == 0 => return error(self.line, column, "Division by zero"),
+                "/" => lhs.checked_div(rhs),
+                _ => lhs.checked_rem(rhs),
+            };
+            let Some(value) = value else {
+                return error(self.line, column, "Overflow");
+            };
+            lhs = value;
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<i64, AsmError> {
+        let column = self.column();
+        let token = self.peek().cloned();
+        self.pos += 1;
+
+        match token {
+            Some(Token::Op("-")) => match self.unary()?.checked_neg() {
+                Some(value) => Ok(value),
+                None => error(self.line, column, "Overflow"),
+            },
+            Some(Token::Op("~")) => Ok(!self.unary()?),
+            Some(Token::Op("(")) => {
+                let value = self.binary(0)?;
+                match self.peek() {
+                    Some(Token::Op(")")) => {
+                        self.pos += 1;
+                        Ok(value)
+                    }
+                    _ => error(self.line, self.column(), "Expected ')'"),
+                }
+            }
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::Ident(name)) => match self.symbols.get(&name) {
+                Some(value) => Ok(*value),
+                None => error(self.line, column, format!("Undefined symbol {}", name)),
+            },
+            Some(t) => error(self.line, column, format!("Unexpected {:?}", t)),
+            None => error(self.line, column, "Expected an expression"),
+        }
+    }
+}
+
+fn evaluate(
+    line: usize,
+    tokens: &[Spanned],
+    symbols: &HashMap<String, i64>,
+) -> Result<i64, AsmError> {
+    let mut expr = Expr {
+        tokens,
+        pos: 0,
+        line,
+        symbols,
+    };
+
+    let value = expr.binary(0)?;
+    if expr.pos < tokens.len() {
+        return error(line, expr.column(), "Unexpected token after expression");
+    }
+
+    Ok(value)
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble_with_symbols(source).map(|(image, _)| image)
+}
+
+pub fn assemble_with_symbols(source: &str) -> Result<(Vec<u8>, HashMap<String, i64>), AsmError> {
+    let mut symbols: HashMap<String, i64> = HashMap::new();
+    let mut statements = vec![];
+    // Constants referring to later labels or constants, resolved after the first pass
+    let mut equs: Vec<Equ> = vec![];
+    let mut addr = ORIGIN as usize;
+
+    // First pass, assign addresses to labels and evaluate the constants we can
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut tokens = &tokenize(line, text)?[..];
+
+        while let [Spanned {
+            token: Token::Ident(label),
+            column,
+        }, Spanned {
+            token: Token::Op(":"),
+            ..
+        }, rest @ ..] = tokens
+        {
+            if equs.iter().any(|equ| equ.name == *label)
+                || symbols.insert(label.clone(), addr as i64).is_some()
+            {
+                return error(line, *column, format!("Duplicate symbol {}", label));
+            }
+            tokens = rest;
+        }
+
+        let (mnemonic, column, rest) = match tokens {
+            [] => continue,
+            [Spanned {
+                token: Token::Ident(name),
+                column,
+            }, Spanned {
+                token: Token::Ident(directive),
+                ..
+            }, rest @ ..]
+                if directive.eq_ignore_ascii_case("EQU") =>
+            {
+                if symbols.contains_key(name) || equs.iter().any(|equ| equ.name == *name) {
+                    return error(line, *column, format!("Duplicate symbol {}", name));
+                }
+                match evaluate(line, rest, &symbols) {
+                    Ok(value) => {
+                        symbols.insert(name.clone(), value);
+                    }
+                    Err(_) => equs.push(Equ {
+                        line,
+                        column: *column,
+                        name: name.clone(),
+                        tokens: rest.to_vec(),
+                    }),
+                }
+                continue;
+            }
+            [Spanned {
+                token: Token::Ident(mnemonic),
+                column,
+            }, rest @ ..] => (mnemonic.to_ascii_uppercase(), *column, rest),
+            [t, ..] => return error(line, t.column, "Expected a mnemonic"),
+        };
+
+        let operands = split_operands(rest);
+
+        let size = match mnemonic.as_str() {
+            "ORG" => {
+                let target = match &operands[..] {
+                    [o] => evaluate(line, o, &symbols)?,
+                    _ => return error(line, column, "ORG takes one address"),
+                };
+                if target < addr as i64 || target > 0xffff {
+                    return error(line, column, format!("Cannot ORG backwards to {}", target));
+                }
+                statements.push(Statement {
+                    line,
+                    column,
+                    addr: addr as u16,
+                    mnemonic,
+                    operands,
+                });
+                addr = target as usize;
+                continue;
+            }
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            _ if is_long(&operands) => 4,
+            _ => 2,
+        };
+
+        if addr + size > 0x10000 {
+            return error(line, column, "Program does not fit in 64K of memory");
+        }
+
+        statements.push(Statement {
+            line,
+            column,
+            addr: addr as u16,
+            mnemonic,
+            operands,
+        });
+        addr += size;
+    }
+
+    resolve(&mut equs, &mut symbols)?;
+
+    // Second pass, emit the image now that every label is known
+    let mut image = vec![];
+    for statement in statements {
+        let offset = (statement.addr - ORIGIN) as usize;
+        image.resize(offset, 0);
+
+        match statement.mnemonic.as_str() {
+            "ORG" => {}
+            "DB" => {
+                for o in &statement.operands {
+                    image.push(value(&statement, o, &symbols, -128, 0xff)? as u8);
+                }
+            }
+            "DW" => {
+                for o in &statement.operands {
+                    let word = value(&statement, o, &symbols, -0x8000, 0xffff)? as u16;
+                    image.extend(word.to_be_bytes());
+                }
+            }
+            _ => image.extend(instruction(&statement, &symbols)?.encode()),
+        }
+    }
+
+    Ok((image, symbols))
+}
+
+// Evaluate constants in whatever order their symbols become known
+fn resolve(equs: &mut Vec<Equ>, symbols: &mut HashMap<String, i64>) -> Result<(), AsmError> {
+    while !equs.is_empty() {
+        let before = equs.len();
+        equs.retain(|equ| match evaluate(equ.line, &equ.tokens, symbols) {
+            Ok(value) => {
+                symbols.insert(equ.name.clone(), value);
+                false
+            }
+            Err(_) => true,
+        });
+
+        if equs.len() == before {
+            // Anything still undefined once the stuck constants exist is a real mistake,
+            // otherwise they must refer to each other
+            let mut known = symbols.clone();
+            known.extend(equs.iter().map(|equ| (equ.name.clone(), 0)));
+            for equ in equs.iter() {
+                evaluate(equ.line, &equ.tokens, &known)?;
+            }
+            return error(
+                equs[0].line,
+                equs[0].column,
+                format!("Circular definition of {}", equs[0].name),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn value(
+    statement: &Statement,
+    tokens: &[Spanned],
+    symbols: &HashMap<String, i64>,
+    min: i64,
+    max: i64,
+) -> Result<i64, AsmError> {
+    let column = tokens.first().map(|t| t.column).unwrap_or(statement.column);
+    let value = evaluate(statement.line, tokens, symbols)?;
+
+    if value < min || value > max {
+        return error(
+            statement.line,
+            column,
+            format!("Value {} out of range {}..={}", value, min, max),
+        );
+    }
+
+    Ok(value)
+}
+
+fn instruction(
+    statement: &Statement,
+    symbols: &HashMap<String, i64>,
+) -> Result<Instruction, AsmError> {
+    use Operand::*;
+
+    let operands: Vec<Operand> = statement.operands.iter().map(|o| classify(o)).collect();
+
+    let addr = |tokens| value(statement, tokens, symbols, 0, 0xfff).map(|v| v as u16);
+    let long = |tokens| value(statement, tokens, symbols, 0, 0xffff).map(|v| v as u16);
+    let byte = |tokens| value(statement, tokens, symbols, -128, 0xff).map(|v| v as u8);
+    let nibble = |tokens| value(statement, tokens, symbols, 0, 0xf).map(|v| v as u8);
+
+    let inst = match (statement.mnemonic.as_str(), &operands[..]) {
+        ("CLS", []) => Instruction::CLS,
+        ("RET", []) => Instruction::RET,
+        ("SCR", []) => Instruction::SCR,
+        ("SCL", []) => Instruction::SCL,
+        ("EXIT", []) => Instruction::EXIT,
+        ("LOW", []) => Instruction::LORES,
+        ("HIGH", []) => Instruction::HIRES,
+        ("AUDIO", []) => Instruction::AUDIO,
+
+        ("SYS", [Expr(a)]) => Instruction::SYS_addr(addr(a)?),
+        ("JP", [Expr(a)]) => Instruction::JP_addr(addr(a)?),
+        ("JP", [Reg(x), Expr(a)]) => {
+            let a = addr(a)?;
+            // BNNN only has room for one register, V0 or the high nibble of the address
+            if *x != 0 && *x as u16 != a >> 8 {
+                return error(
+                    statement.line,
+                    statement.column,
+                    "JP Vx, addr needs V0 or x to match the high nibble of addr",
+                );
+            }
+            Instruction::JP_Vx_addr((a >> 8) as u8, a)
+        }
+        ("CALL", [Expr(a)]) => Instruction::CALL_addr(addr(a)?),
+
+        ("SE", [Reg(x), Reg(y)]) => Instruction::SE_Vx_Vy(*x, *y),
+        ("SE", [Reg(x), Expr(kk)]) => Instruction::SE_Vx_kk(*x, byte(kk)?),
+        ("SNE", [Reg(x), Reg(y)]) => Instruction::SNE_Vx_Vy(*x, *y),
+        ("SNE", [Reg(x), Expr(kk)]) => Instruction::SNE_Vx_kk(*x, byte(kk)?),
+
+        ("LD", [Reg(x), Reg(y)]) => Instruction::LD_Vx_Vy(*x, *y),
+        ("LD", [Reg(x), Expr(kk)]) => Instruction::LD_Vx_kk(*x, byte(kk)?),
+        ("LD", [Name("I"), Expr(a)]) => Instruction::LD_I_addr(addr(a)?),
+        ("LD", [Name("I"), Long(a)]) => Instruction::LD_I_long(long(a)?),
+        ("LD", [Reg(x), Name("DT")]) => Instruction::LD_Vx_DT(*x),
+        ("LD", [Reg(x), Name("K")]) => Instruction::LD_Vx_K(*x),
+        ("LD", [Name("DT"), Reg(x)]) => Instruction::LD_DT_Vx(*x),
+        ("LD", [Name("ST"), Reg(x)]) => Instruction::LD_ST_Vx(*x),
+        ("LD", [Name("F"), Reg(x)]) => Instruction::LD_F_Vx(*x),
+        ("LD", [Name("HF"), Reg(x)]) => Instruction::LD_HF_Vx(*x),
+        ("LD", [Name("B"), Reg(x)]) => Instruction::LD_B_Vx(*x),
+        ("LD", [Name("[I]"), Reg(x)]) => Instruction::LD_iI_Vx(*x),
+        ("LD", [Reg(x), Name("[I]")]) => Instruction::LD_Vx_iI(*x),
+        ("LD", [Name("R"), Reg(x)]) => Instruction::SAVE_Vx(*x),
+        ("LD", [Reg(x), Name("R")]) => Instruction::LOAD_Vx(*x),
+
+        ("ADD", [Reg(x), Reg(y)]) => Instruction::ADD_Vx_Vy(*x, *y),
+        ("ADD", [Reg(x), Expr(kk)]) => Instruction::ADD_Vx_kk(*x, byte(kk)?),
+        ("ADD", [Name("I"), Reg(x)]) => Instruction::ADD_I_Vx(*x),
+        ("OR", [Reg(x), Reg(y)]) => Instruction::OR_Vx_Vy(*x, *y),
+        ("AND", [Reg(x), Reg(y)]) => Instruction::AND_Vx_Vy(*x, *y),
+        ("XOR", [Reg(x), Reg(y)]) => Instruction::XOR_Vx_Vy(*x, *y),
+        ("SUB", [Reg(x), Reg(y)]) => Instruction::SUB_Vx_Vy(*x, *y),
+        ("SUBN", [Reg(x), Reg(y)]) => Instruction::SUBN_Vx_Vy(*x, *y),
+        ("SHR", [Reg(x)]) => Instruction::SHR_Vx_Vy(*x, *x),
+        ("SHR", [Reg(x), Reg(y)]) => Instruction::SHR_Vx_Vy(*x, *y),
+        ("SHL", [Reg(x)]) => Instruction::SHL_Vx_Vy(*x, *x),
+        ("SHL", [Reg(x), Reg(y)]) => Instruction::SHL_Vx_Vy(*x, *y),
+
+        ("RND", [Reg(x), Expr(kk)]) => Instruction::RND_Vx_kk(*x, byte(kk)?),
+        ("DRW", [Reg(x), Reg(y), Expr(n)]) => Instruction::DRW_Vx_Vy_n(*x, *y, nibble(n)?),
+        ("SKP", [Reg(x)]) => Instruction::SKP_Vx(*x),
+        ("SKNP", [Reg(x)]) => Instruction::SKNP_Vx(*x),
+
+        ("SCD", [Expr(n)]) => Instruction::SCD_n(nibble(n)?),
+        ("SCU", [Expr(n)]) => Instruction::SCU_n(nibble(n)?),
+        ("SAVE", [Reg(x), Reg(y)]) => Instruction::LD_iI_Vx_Vy(*x, *y),
+        ("LOAD", [Reg(x), Reg(y)]) => Instruction::LD_Vx_Vy_iI(*x, *y),
+        ("PLANE", [Expr(n)]) => Instruction::PLANE_n(value(statement, n, symbols, 0, 3)? as u8),
+        ("PITCH", [Reg(x)]) => Instruction::PITCH_Vx(*x),
+
+        (mnemonic, _) => {
+            return error(
+                statement.line,
+                statement.column,
+                format!("Invalid operands for {}", mnemonic),
+            )
+        }
+    };
+
+    Ok(inst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, AsmError};
+    use crate::{
+        disassembler::{disassemble, Syntax},
+        memory::Memory,
+    };
+
+    #[test]
+    fn assembles_instructions() {
+        let image = assemble(
+            "
+            CLS
+            LD V1, #2A
+            DRW V1, V2, 5
+            LD I, LONG #1234
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            image,
+            [0x00, 0xE0, 0x61, 0x2A, 0xD1, 0x25, 0xF0, 0x00, 0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn assembles_labels_and_data() {
+        let image = assemble(
+            "
+            start: JP end        ; forward reference
+            sprite: DB 0b1, 2, $3
+                    DW #1234, sprite + 1
+            end:    JP start
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            image,
+            [0x12, 0x09, 0x01, 0x02, 0x03, 0x12, 0x34, 0x02, 0x03, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn assembles_constant_expressions() {
+        let image = assemble(
+            "
+            WIDTH EQU 64
+            HALF  EQU WIDTH / 2
+                  LD V0, (HALF - 4) * 2 | 1
+                  ORG #208
+                  DB ~0 & #0F, -1, 1 << 4
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            image,
+            [0x60, 0x39, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0F, 0xFF, 0x10]
+        );
+    }
+
+    #[test]
+    fn constants_can_refer_forward() {
+        let image = assemble(
+            "
+            SIZE  EQU END - START
+            START: LD V0, SIZE
+            COUNT EQU SIZE / 2
+                   LD V1, COUNT
+            END:
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(image, [0x60, 0x04, 0x61, 0x02]);
+
+        assert_eq!(
+            assemble(
+                "A EQU B + 1
+B EQU A"
+            )
+            .unwrap_err()
+            .to_string(),
+            "1:1: Circular definition of A"
+        );
+        assert_eq!(
+            assemble(
+                "A EQU B
+B EQU missing"
+            )
+            .unwrap_err()
+            .to_string(),
+            "2:7: Undefined symbol missing"
+        );
+        assert_eq!(
+            assemble(
+                "A EQU 1
+A: CLS"
+            )
+            .unwrap_err()
+            .to_string(),
+            "2:1: Duplicate symbol A"
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_line_and_column() {
+        assert_eq!(
+            assemble("CLS\n  LD V1, missing"),
+            Err(AsmError {
+                line: 2,
+                column: 10,
+                message: "Undefined symbol missing".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("  LD V1, #100").unwrap_err().to_string(),
+            "1:10: Value 256 out of range -128..=255"
+        );
+        assert_eq!(
+            assemble("  FOO V1").unwrap_err().to_string(),
+            "1:3: Invalid operands for FOO"
+        );
+        for source in [
+            "DB 9223372036854775807 + 1",
+            "DB -(-9223372036854775807 - 1)",
+            "DB (-9223372036854775807 - 1) % -1",
+        ] {
+            assert!(
+                assemble(source)
+                    .unwrap_err()
+                    .to_string()
+                    .ends_with(": Overflow"),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn assembles_the_ibm_logo_source() {
+        assert_eq!(
+            assemble(include_str!("test_data/2-ibm-logo.asm")).unwrap(),
+            include_bytes!("test_data/2-ibm-logo.ch8")
+        );
+    }
+
+    macro_rules! round_trip_test {
+        ($($func_name:ident: $rom_name:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    const PROGRAM: &[u8] = include_bytes!(concat!("test_data/", $rom_name, ".ch8"));
+
+                    let mut m = Memory::new();
+                    m.load_program(PROGRAM).unwrap();
+
+                    let source = disassemble(&m, 0x200..(0x200 + PROGRAM.len()), Syntax::Cowgod)
+                        .iter()
+                        .map(|line| line.text.clone())
+                        .collect::<Vec<String>>()
+                        .join("\n");
+
+                    assert_eq!(assemble(&source).unwrap(), PROGRAM);
+                }
+            )*
+        };
+    }
+
+    round_trip_test! {
+        round_trip_chip8_logo: "1-chip8-logo",
+        round_trip_corax: "3-corax+",
+        round_trip_flags: "4-flags",
+        round_trip_quirks: "5-quirks",
+    }
+}
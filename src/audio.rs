@@ -0,0 +1,101 @@
+use super::cpu::CPU;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+// Default pattern buffer, a 250Hz square wave until a ROM loads its own
+pub const SQUARE_WAVE: [u8; 16] = [
+    0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00,
+];
+
+pub struct Audio {
+    pub sample_rate: u32,
+    pub volume: f32,
+
+    // Position within the 128 bit pattern buffer
+    position: f64,
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Audio {
+    pub fn new(sample_rate: u32) -> Audio {
+        Audio {
+            sample_rate,
+            volume: 0.25,
+            position: 0.0,
+        }
+    }
+
+    // XO-CHIP playback rate, 4000 bits per second at the default pitch of 64
+    pub fn playback_rate(pitch: u8) -> f64 {
+        4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+    }
+
+    // Fill `buffer` with the samples for the current sound timer state
+    pub fn fill(&mut self, cpu: &CPU, buffer: &mut [f32]) {
+        if cpu.sound_timer() == 0 {
+            self.position = 0.0;
+            buffer.fill(0.0);
+            return;
+        }
+
+        let step = Audio::playback_rate(cpu.pitch) / self.sample_rate as f64;
+
+        for sample in buffer.iter_mut() {
+            let bit = self.position as usize;
+            let set = cpu.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+
+            *sample = if set { self.volume } else { -self.volume };
+
+            self.position = (self.position + step) % 128.0;
+        }
+    }
+
+    pub fn samples(&mut self, cpu: &CPU, count: usize) -> Vec<f32> {
+        let mut buffer = vec![0.0; count];
+        self.fill(cpu, &mut buffer);
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Audio;
+    use crate::{cpu::CPU, instruction::Instruction};
+
+    #[test]
+    fn audio_is_silent_without_sound_timer() {
+        let cpu = CPU::new();
+        let mut audio = Audio::new(8000);
+
+        assert!(audio.samples(&cpu, 100).iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn audio_plays_the_pattern_buffer() {
+        let mut cpu = CPU::new();
+        let mut audio = Audio::new(8000);
+
+        cpu.pattern = [0b10100000; 16];
+        cpu.execute(Instruction::LD_Vx_kk(0, 2));
+        cpu.execute(Instruction::LD_ST_Vx(0));
+
+        // 4000 bits per second at 8000Hz plays each bit for 2 samples
+        let v = audio.volume;
+        assert_eq!(
+            audio.samples(&cpu, 10),
+            [v, v, -v, -v, v, v, -v, -v, -v, -v]
+        );
+    }
+
+    #[test]
+    fn audio_follows_the_pitch_register() {
+        assert_eq!(Audio::playback_rate(64), 4000.0);
+        assert_eq!(Audio::playback_rate(112), 8000.0);
+        assert_eq!(Audio::playback_rate(16), 2000.0);
+    }
+}
@@ -0,0 +1,63 @@
+extern crate chip8;
+
+use chip8::assembler::assemble;
+use chip8::octo::compile;
+
+use std::path::Path;
+use std::process::exit;
+
+pub fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut input: Option<String> = None;
+    let mut output: Option<String> = None;
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = iter.next().cloned(),
+            filename if input.is_none() => input = Some(filename.to_string()),
+            x => {
+                eprintln!("Invalid argument {}", x);
+                exit(2);
+            }
+        }
+    }
+
+    let Some(input) = input else {
+        eprintln!("Usage: chip8-asm <source.asm|source.8o> [-o <output.ch8>]");
+        exit(2);
+    };
+    let output = output.unwrap_or_else(|| {
+        Path::new(&input)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    let source = std::fs::read_to_string(&input).unwrap_or_else(|err| {
+        eprintln!("Unable to read {}: {}", input, err);
+        exit(1);
+    });
+
+    // Octo sources are compiled, anything else is Cowgod assembly
+    let result = if input.ends_with(".8o") {
+        compile(&source).map(|program| program.image)
+    } else {
+        assemble(&source)
+    };
+
+    match result {
+        Ok(image) => {
+            std::fs::write(&output, &image).unwrap_or_else(|err| {
+                eprintln!("Unable to write {}: {}", output, err);
+                exit(1);
+            });
+            println!("Wrote {} bytes to {}", image.len(), output);
+        }
+        Err(err) => {
+            eprintln!("{}:{}", input, err);
+            exit(1);
+        }
+    }
+}
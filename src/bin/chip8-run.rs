@@ -0,0 +1,238 @@
+extern crate chip8;
+
+use chip8::{
+    analyzer::analyze,
+    config::Config,
+    fault::FaultPolicies,
+    memory::{Memory, MEMORY_64K},
+    platform::Platform,
+    quirks::Quirks,
+    romdb::RomDatabase,
+    screenshot::{self, DEFAULT_PALETTE},
+    Chip8,
+};
+
+use std::path::Path;
+use std::process::exit;
+
+const USAGE: &str = "Usage: chip8-run <rom.ch8> [--chip8|--superchip|--xochip] [--platform=NAME] [--config=file]
+                 [--database=programs.json] [--detect] [--set=addr:val,...] [--break=addr,...] [--frames=N] [--seed=N] [--strict] [--image=out.png|svg|pbm] [--scale=N] [--quiet]";
+
+fn usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    exit(2);
+}
+
+fn hex<T: TryFrom<u32>>(s: &str, what: &str) -> T {
+    u32::from_str_radix(s, 16)
+        .ok()
+        .and_then(|v| T::try_from(v).ok())
+        .unwrap_or_else(|| usage(&format!("Invalid {} {}", what, s)))
+}
+
+fn print_registers(chip8: &Chip8) {
+    let cpu = &chip8.cpu;
+    println!("{}", cpu);
+    for (x, v) in cpu.registers().iter().enumerate() {
+        print!(
+            "V{:X}={:02x}{}",
+            x,
+            v,
+            if x == 7 || x == 15 { "\n" } else { " " }
+        );
+    }
+    println!(
+        "stack=[{}]",
+        cpu.stack()
+            .iter()
+            .map(|addr| format!("{:04x}", addr))
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+}
+
+pub fn main() {
+    let mut chip8 = Chip8::new();
+
+    let mut rom: Option<String> = None;
+    let mut frames = 600u32;
+    let mut image: Option<String> = None;
+    let mut scale = 8u32;
+    let mut quiet = false;
+    let mut config = Config::default();
+    let mut database: Option<RomDatabase> = None;
+    // Settings given on the command line win over the database
+    let mut configured = false;
+    let mut detect = false;
+    let mut set_values: Vec<(u16, u8)> = vec![];
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--chip8" | "--quirks=chip8" => {
+                chip8.cpu.quirks = Quirks::chip8();
+                configured = true;
+            }
+            "--superchip" | "--quirks=superchip" => {
+                chip8.cpu.quirks = Quirks::superchip();
+                configured = true;
+            }
+            "--xochip" | "--quirks=xochip" => {
+                chip8.cpu.quirks = Quirks::xochip();
+                chip8.cpu.memory = Memory::with_size(MEMORY_64K).unwrap();
+                configured = true;
+            }
+            path if arg.starts_with("--config=") => {
+                config = Config::load(Path::new(&path[9..]))
+                    .unwrap_or_else(|err| usage(&err.to_string()));
+                config
+                    .apply(&mut chip8)
+                    .unwrap_or_else(|err| usage(&err.to_string()));
+                configured = true;
+            }
+            platform if arg.starts_with("--platform=") => {
+                chip8
+                    .set_platform(
+                        &platform[11..]
+                            .parse::<Platform>()
+                            .unwrap_or_else(|err| usage(&err.to_string())),
+                    )
+                    .unwrap_or_else(|err| usage(&err.to_string()));
+                configured = true;
+            }
+            path if arg.starts_with("--database=") => {
+                database = Some(
+                    RomDatabase::load(Path::new(&path[11..]))
+                        .unwrap_or_else(|err| usage(&err.to_string())),
+                )
+            }
+            "--detect" => detect = true,
+            "--strict" => chip8.cpu.faults = FaultPolicies::strict(),
+            "--quiet" | "-q" => quiet = true,
+
+            frames_arg if arg.starts_with("--frames=") => {
+                frames = frames_arg[9..]
+                    .parse()
+                    .unwrap_or_else(|_| usage(&format!("Invalid frame count {}", &frames_arg[9..])))
+            }
+            seed if arg.starts_with("--seed=") => chip8.seed(
+                seed[7..]
+                    .parse()
+                    .unwrap_or_else(|_| usage(&format!("Invalid seed {}", &seed[7..]))),
+            ),
+            path if arg.starts_with("--image=") => image = Some(path[8..].to_string()),
+            scale_arg if arg.starts_with("--scale=") => {
+                scale = scale_arg[8..]
+                    .parse()
+                    .unwrap_or_else(|_| usage(&format!("Invalid scale {}", &scale_arg[8..])))
+            }
+
+            // --set=200:00,201:e0
+            set if arg.starts_with("--set=") => {
+                for pair in set[6..].split(',') {
+                    let Some((addr, val)) = pair.split_once(':') else {
+                        usage(&format!("Invalid set {}", pair));
+                    };
+                    set_values.push((hex(addr, "address"), hex(val, "value")));
+                }
+            }
+            // --break=feed,123,42
+            bp if arg.starts_with("--break=") => {
+                for addr in bp[8..].split(',') {
+                    chip8.set_breakpoint(hex(addr, "breakpoint"));
+                }
+            }
+
+            filename if rom.is_none() && !filename.starts_with('-') => {
+                rom = Some(filename.to_string())
+            }
+            x => usage(&format!("Invalid argument {}", x)),
+        }
+    }
+
+    let Some(rom) = rom else {
+        usage("No ROM given");
+    };
+    let program = std::fs::read(&rom).unwrap_or_else(|err| {
+        eprintln!("Unable to read {}: {}", rom, err);
+        exit(1);
+    });
+
+    if !configured {
+        match database
+            .as_ref()
+            .and_then(|db| chip8.configure_from(db, &program))
+        {
+            Some(info) => {
+                config = info.config.clone();
+                if !quiet {
+                    println!("Identified {}", info.title);
+                }
+            }
+            None if detect => {
+                let analysis = analyze(&program);
+                chip8
+                    .set_platform(&analysis.platform)
+                    .unwrap_or_else(|err| usage(&err.to_string()));
+                chip8.cpu.quirks = analysis.quirks;
+                if !quiet {
+                    println!(
+                        "Detected {} ({:.0}% sure)",
+                        analysis.platform,
+                        analysis.confidence * 100.0
+                    );
+                }
+                if analysis.hires_vip {
+                    eprintln!(
+                        "Warning: written for the 64x64 VIP interpreter, which isn't emulated"
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+
+    chip8.load_program(&program).unwrap_or_else(|err| {
+        eprintln!("Unable to load {}: {}", rom, err);
+        exit(1);
+    });
+    for (addr, val) in set_values {
+        chip8.cpu.memory.write(addr, val);
+    }
+
+    let mut fault = None;
+    let mut frame = 0;
+    while frame < frames && chip8.cpu.running && !chip8.halted {
+        if let Err(err) = chip8.tick() {
+            fault = Some(err);
+            break;
+        }
+        frame += 1;
+    }
+
+    if !quiet {
+        println!("{}", chip8.cpu.display_text());
+        println!();
+    }
+
+    let reason = match fault {
+        Some(fault) => format!("fault: {}", fault),
+        None if chip8.halted => format!("breakpoint at {:04x}", chip8.cpu.pc),
+        None if !chip8.cpu.running => "exited".to_string(),
+        None => "frame limit".to_string(),
+    };
+    println!("Stopped after {} frames ({})", frame, reason);
+    print_registers(&chip8);
+
+    if let Some(path) = image {
+        let palette = config.palette.as_deref().unwrap_or(&DEFAULT_PALETTE);
+        screenshot::save(&chip8.cpu, Path::new(&path), palette, scale).unwrap_or_else(|err| {
+            eprintln!("Unable to write {}: {}", path, err);
+            exit(1);
+        });
+    }
+
+    if fault.is_some() {
+        exit(1);
+    }
+}
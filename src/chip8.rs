@@ -1,14 +1,45 @@
 use std::collections::HashSet;
 
-use super::cpu::CPU;
-use log::{self, info};
-
+use super::{
+    cpu::CPU,
+    fault::Fault,
+    memory::{InvalidMemorySize, Memory, ProgramTooLarge},
+    movie::{checksum, sha1_hex, KeyEvent, Movie, MovieError, Player},
+    platform::Platform,
+    rewind::Rewind,
+    romdb::{RomDatabase, RomInfo},
+    savestate::{self, SaveStateError},
+};
+use log::{self, info, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
 pub struct Chip8 {
     pub cpu: CPU,
 
     pub halted: bool,
 
     breakpoints: HashSet<u16>,
+
+    #[serde(skip)]
+    pub rewind: Option<Rewind>,
+
+    // Frames run since recording or playback started
+    #[serde(skip)]
+    frame: u64,
+    // Cycles run so far in the current frame
+    #[serde(skip)]
+    cycle: u32,
+    #[serde(skip)]
+    recording: Option<Movie>,
+    #[serde(skip)]
+    player: Option<Player>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chip8 {
@@ -17,24 +48,61 @@ impl Chip8 {
             cpu: CPU::new(),
             breakpoints: HashSet::new(),
             halted: false,
+            rewind: None,
+            frame: 0,
+            cycle: 0,
+            recording: None,
+            player: None,
         }
     }
 
-    pub fn load_program(&mut self, program: &[u8]) {
-        self.cpu.memory.load_program(program);
+    // Call before `load_program`, the platform replaces memory
+    pub fn set_platform(&mut self, platform: &Platform) -> Result<(), InvalidMemorySize> {
+        self.cpu.set_platform(platform)
+    }
+
+    // Set up for `program` if the database knows it, call before `load_program`
+    pub fn configure_from<'a>(
+        &mut self,
+        database: &'a RomDatabase,
+        program: &[u8],
+    ) -> Option<&'a RomInfo> {
+        let rom = database.lookup(program)?;
+        info!("Identified {}", rom.title);
+        // Entries only name preset platforms so this can't fail
+        rom.config.apply(self).ok()?;
+        Some(rom)
     }
 
-    pub fn tick(&mut self) {
+    pub fn seed(&mut self, seed: u64) {
+        self.cpu.seed(seed);
+    }
+
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), ProgramTooLarge> {
+        self.cpu.memory.load_program(program)
+    }
+
+    pub fn tick(&mut self) -> Result<(), Fault> {
         if !self.cpu.running || self.halted {
-            return;
+            return Ok(());
         }
 
-        // 1_000_000 / 600 =
-        let max_cycles = self.cpu.clock_speed / 6000; // Cycles to run per tick
-        let mut cycles = 0;
+        let max_cycles = self.cpu.timing.frame_budget(self.cpu.clock_speed); // Cycles to run per tick
+
+        // The vertical blank interrupt releases any DRW waiting on it
+        self.cpu.vblank_wait = false;
+        self.play_input(self.cycle);
 
-        while self.cpu.running && cycles < max_cycles {
-            cycles += self.cpu.step();
+        while self.cpu.running && self.cycle < max_cycles {
+            self.cycle += match self.cpu.step() {
+                Ok(cycles) => cycles,
+                Err(fault) => {
+                    warn!("{}, halting", fault);
+                    self.halted = true;
+                    return Err(fault);
+                }
+            };
+            self.play_input(self.cycle);
 
             if self.breakpoints.contains(&self.cpu.pc) {
                 info!("Breakpoint hit at {}", self.cpu.pc);
@@ -42,20 +110,212 @@ impl Chip8 {
                 break;
             }
 
-            if cycles >= max_cycles {
+            if self.cycle >= max_cycles || self.cpu.vblank_wait {
                 break;
             }
         }
 
+        self.end_frame();
+
+        Ok(())
+    }
+
+    // Run one instruction, finishing the frame once it has used up its cycles
+    pub fn step(&mut self) -> Result<u32, Fault> {
+        let cycles = self.cpu.step()?;
+        self.cycle += cycles;
+        self.play_input(self.cycle);
+
+        if self.cycle >= self.cpu.timing.frame_budget(self.cpu.clock_speed) || self.cpu.vblank_wait
+        {
+            self.cpu.vblank_wait = false;
+            self.end_frame();
+        }
+
+        Ok(cycles)
+    }
+
+    fn end_frame(&mut self) {
         self.cpu.tick_timers();
+
+        if let Some(rewind) = &mut self.rewind {
+            rewind.record(&self.cpu);
+        }
+
+        self.frame += 1;
+        self.cycle = 0;
+    }
+
+    fn play_input(&mut self, cycle: u32) {
+        if let Some(player) = &mut self.player {
+            for event in player.due(self.frame, cycle) {
+                if event.down {
+                    self.cpu.keydown(event.key);
+                } else {
+                    self.cpu.keyup(event.key);
+                }
+            }
+        }
+    }
+
+    // Record input from here on, the ROM should be freshly loaded
+    pub fn start_recording(&mut self, rom: &[u8], seed: u64) {
+        self.seed(seed);
+        self.frame = 0;
+        self.cycle = 0;
+        self.recording = Some(Movie::new(rom, &self.cpu, seed));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        let mut movie = self.recording.take()?;
+        movie.frames = self.frame;
+        movie.checksum = checksum(&self.cpu);
+        Some(movie)
+    }
+
+    // Replay a movie over a freshly loaded ROM, live input is ignored until it finishes
+    pub fn start_playback(&mut self, movie: Movie, rom: &[u8]) -> Result<(), MovieError> {
+        let found = sha1_hex(rom);
+        if found != movie.rom_sha1 {
+            return Err(MovieError::RomMismatch {
+                expected: movie.rom_sha1,
+                found,
+            });
+        }
+
+        let mut memory = Memory::with_size(movie.memory_size)
+            .map_err(|err| MovieError::Decode(err.to_string()))?;
+        memory
+            .load_program(rom)
+            .map_err(|err| MovieError::Decode(err.to_string()))?;
+        for &(addr, byte) in &movie.memory {
+            memory.write(addr, byte);
+        }
+
+        self.cpu.memory = memory;
+        self.cpu.quirks = movie.quirks.clone();
+        self.cpu.timing = movie.timing;
+        self.cpu.clock_speed = movie.clock_speed;
+        self.cpu.stack_depth = movie.stack_depth;
+        self.cpu.opcodes = movie.opcodes;
+        self.seed(movie.seed);
+        self.frame = 0;
+        self.cycle = 0;
+        self.player = Some(Player::new(movie));
+        Ok(())
+    }
+
+    pub fn playback_finished(&self) -> bool {
+        match &self.player {
+            Some(player) => player.finished(self.frame),
+            None => true,
+        }
+    }
+
+    // Stop playback and check the machine ended up where the recording did
+    pub fn verify_playback(&mut self) -> Result<(), MovieError> {
+        let Some(player) = self.player.take() else {
+            return Ok(());
+        };
+
+        let found = checksum(&self.cpu);
+        if found != player.movie.checksum {
+            return Err(MovieError::Desync {
+                expected: player.movie.checksum,
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    fn record_input(&mut self, key: u8, down: bool) {
+        if let Some(movie) = &mut self.recording {
+            movie.events.push(KeyEvent {
+                frame: self.frame,
+                cycle: self.cycle,
+                key,
+                down,
+            });
+        }
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::to_bytes(self)
+    }
+
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        let state = savestate::from_bytes(bytes)?;
+        self.restore(state);
+        Ok(())
+    }
+
+    pub fn save_state_json(&self) -> String {
+        savestate::to_json(self)
+    }
+
+    pub fn load_state_json(&mut self, json: &str) -> Result<(), SaveStateError> {
+        let state = savestate::from_json(json)?;
+        self.restore(state);
+        Ok(())
+    }
+
+    fn restore(&mut self, state: Chip8) {
+        let Chip8 {
+            cpu,
+            halted,
+            breakpoints,
+            ..
+        } = state;
+
+        self.cpu.restore(cpu);
+        self.halted = halted;
+        self.breakpoints = breakpoints;
+    }
+
+    // Snapshot every `interval` frames, keeping at most `budget` bytes of history
+    pub fn enable_rewind(&mut self, interval: u32, budget: usize) {
+        self.rewind = Some(Rewind::new(interval, budget));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    pub fn step_back(&mut self) -> bool {
+        let cpu = self.rewind.as_mut().and_then(|rewind| rewind.back(1));
+        self.rewind_to(cpu)
+    }
+
+    pub fn rewind_seconds(&mut self, seconds: f32) -> bool {
+        let cpu = self
+            .rewind
+            .as_mut()
+            .and_then(|rewind| rewind.back_seconds(seconds));
+        self.rewind_to(cpu)
+    }
+
+    fn rewind_to(&mut self, cpu: Option<CPU>) -> bool {
+        match cpu {
+            Some(cpu) => {
+                self.cpu.restore(cpu);
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn keydown(&mut self, key: u8) {
-        self.cpu.keydown(key);
+        if self.player.is_none() {
+            self.record_input(key, true);
+            self.cpu.keydown(key);
+        }
     }
 
     pub fn keyup(&mut self, key: u8) {
-        self.cpu.keyup(key);
+        if self.player.is_none() {
+            self.record_input(key, false);
+            self.cpu.keyup(key);
+        }
     }
 
     pub fn resume(&mut self) {
@@ -73,24 +333,15 @@ impl Chip8 {
 
 #[cfg(test)]
 mod tests {
+    use std::ops::Range;
+
     use pretty_assertions::assert_eq;
 
-    use crate::{quirks::Quirks, Chip8};
+    use crate::{fault::Fault, quirks::Quirks, timing::Timing, Chip8};
 
     macro_rules! assert_vram_matches {
         ($chip8:expr, $expected_results:expr) => {
-            let lhs = $chip8
-                .cpu
-                .vram
-                .chunks_exact($chip8.cpu.width)
-                .map(|r| {
-                    r.iter()
-                        .map(|v| if *v { "█" } else { " " })
-                        .collect::<Vec<&str>>()
-                        .join("")
-                })
-                .collect::<Vec<String>>()
-                .join("\n");
+            let lhs = $chip8.cpu.display_text();
 
             assert_eq!(
                 lhs, $expected_results,
@@ -99,9 +350,39 @@ mod tests {
         };
     }
 
+    // The result column of the quirks test ROM, as "ON" or "OFF" with a checkmark
+    const ON_PASSED: [&str; 4] = [
+        "███ ██              ",
+        "█ █ █ █        █ █  ",
+        "█ █ █ █        ██   ",
+        "███ █ █        █    ",
+    ];
+    const OFF_PASSED: [&str; 4] = [
+        "███ ███ ███         ",
+        "█ █ █   █      █ █  ",
+        "█ █ ██  ██     ██   ",
+        "███ █   █      █    ",
+    ];
+
+    fn display_region(chip8: &Chip8, rows: Range<usize>, columns: Range<usize>) -> Vec<String> {
+        rows.map(|y| {
+            columns
+                .clone()
+                .map(|x| {
+                    if chip8.cpu.vram[y * chip8.cpu.width + x] != 0 {
+                        "█"
+                    } else {
+                        " "
+                    }
+                })
+                .collect()
+        })
+        .collect()
+    }
+
     fn run_until_finished(chip8: &mut Chip8, cycles: u32) -> Result<u32, String> {
         for i in 0..cycles {
-            chip8.tick();
+            chip8.tick().map_err(|fault| fault.to_string())?;
 
             if !chip8.cpu.running {
                 return Ok(i);
@@ -111,6 +392,90 @@ mod tests {
         Err(format!("Did not exit in {} cycles", cycles))
     }
 
+    #[test]
+    fn invalid_opcodes_halt() {
+        let mut c = Chip8::new();
+        c.load_program(&[0x00, 0xe0, 0xff, 0xff]).unwrap();
+
+        assert_eq!(
+            c.tick(),
+            Err(Fault::InvalidOpcode {
+                op: 0xffff,
+                addr: 0x202
+            })
+        );
+        assert!(c.halted, "Chip8 should halt on a fault");
+        assert_eq!(c.tick(), Ok(()), "Halted Chip8 should not run");
+    }
+
+    #[test]
+    fn vip_timing_budgets_by_instruction_cost() {
+        // A long run of LD V0, 1
+        let program = [0x60, 0x01].repeat(200);
+
+        let mut c = Chip8::new();
+        c.load_program(&program).unwrap();
+        c.tick().unwrap();
+        assert_eq!(c.cpu.pc, 0x200 + 21 * 2);
+
+        let mut c = Chip8::new();
+        c.cpu.timing = Timing::Vip;
+        c.load_program(&program).unwrap();
+        c.tick().unwrap();
+        assert_eq!(c.cpu.pc, 0x200 + 58 * 2);
+    }
+
+    #[test]
+    fn display_wait_ends_the_frame() {
+        // DRW V0, V0, 1 forever
+        let program = [0xd0, 0x01].repeat(10);
+
+        let mut c = Chip8::new();
+        c.cpu.quirks = Quirks::chip8();
+        c.load_program(&program).unwrap();
+
+        c.tick().unwrap();
+        assert_eq!(c.cpu.pc, 0x202, "Only one sprite should be drawn per frame");
+        assert!(c.cpu.vblank_wait);
+
+        c.tick().unwrap();
+        assert_eq!(c.cpu.pc, 0x204);
+
+        c.cpu.quirks = Quirks::xochip();
+        c.tick().unwrap();
+        assert!(c.cpu.pc > 0x206, "Without display wait sprites draw freely");
+    }
+
+    #[test]
+    fn rewinding() {
+        // Count V0 up forever, storing it at $300
+        let mut c = Chip8::new();
+        c.load_program(&[0x70, 0x01, 0xa3, 0x00, 0xf0, 0x55, 0x12, 0x00])
+            .unwrap();
+        assert!(!c.step_back(), "Nothing to rewind without history");
+
+        c.enable_rewind(1, 1024 * 1024);
+        let mut counts = vec![];
+        for _ in 0..120 {
+            c.tick().unwrap();
+            counts.push(c.cpu.memory.read(0x300));
+        }
+
+        assert!(c.step_back());
+        assert_eq!(c.cpu.memory.read(0x300), counts[118]);
+        assert!(c.step_back());
+        assert_eq!(c.cpu.memory.read(0x300), counts[117]);
+
+        assert!(c.rewind_seconds(1.0));
+        assert_eq!(c.cpu.memory.read(0x300), counts[57]);
+
+        // Running again from the rewound state is the same as the first time
+        c.tick().unwrap();
+        assert_eq!(c.cpu.memory.read(0x300), counts[58]);
+        assert!(c.step_back());
+        assert_eq!(c.cpu.memory.read(0x300), counts[57]);
+    }
+
     macro_rules! test_roms {
         ($($func_name:ident: $rom_name:expr,)*)=>{
         $(
@@ -120,7 +485,7 @@ mod tests {
                 const RESULTS: &str = include_str!(concat!("test_data/", $rom_name, ".txt"));
 
                 let mut c = Chip8::new();
-                c.load_program(PROGRAM);
+                c.load_program(PROGRAM).unwrap();
 
                 run_until_finished(&mut c, 1000).expect("Did not finish");
                 assert_vram_matches!(c, RESULTS);
@@ -141,23 +506,28 @@ mod tests {
     }
 
     macro_rules! quirks_test {
-        ($($func_name:ident: ($quirk:ident, $mode_value:expr),)*)=>{
+        ($($func_name:ident: ($quirk:ident, $mode_value:expr, $display_wait:expr),)*)=>{
             $(
                 #[test]
                 pub fn $func_name() {
                     let mut c = Chip8::new();
                     c.cpu.quirks = Quirks::$quirk();
-                    c.load_program(include_bytes!("test_data/5-quirks.ch8"));
+                    c.load_program(include_bytes!("test_data/5-quirks.ch8")).unwrap();
                     c.cpu.memory.write(0x1ff, $mode_value); // Set chip8 mode
                     c.set_breakpoint(0x05d2);
 
                     for _ in 0..1000 {
-                        c.tick();
+                        c.tick().expect("Fault");
                         if c.halted {
                             break;
                         }
                     }
 
+                    assert_eq!(
+                        display_region(&c, 11..15, 44..64),
+                        $display_wait,
+                        "Display wait should be detected and pass"
+                    );
                     assert_vram_matches!(c, include_str!(concat!("test_data/5-quirks-", stringify!($quirk), ".txt")));
                 }
             )*
@@ -165,11 +535,14 @@ mod tests {
     }
 
     quirks_test! {
-        quirks_chip8: (chip8, 1),
-        quirks_superchip: (superchip, 2),
-        quirks_xochip: (xochip, 3),
+        quirks_chip8: (chip8, 1, ON_PASSED),
+        quirks_superchip: (superchip, 2, OFF_PASSED),
+        quirks_xochip: (xochip, 3, OFF_PASSED),
     }
 
+    // The ROM only checks the original quirks in hires. Flipping the half scroll, 8x16
+    // sprite, collided rows, I += X or resolution clear quirks doesn't change its
+    // results, so those are covered by the CPU tests instead
     macro_rules! hires_quirks_test {
         ($($func_name:ident: ($quirk:ident, $mode_value:expr),)*)=>{
             $(
@@ -177,12 +550,12 @@ mod tests {
                 pub fn $func_name() {
                     let mut c = Chip8::new();
                     c.cpu.quirks = Quirks::$quirk();
-                    c.load_program(include_bytes!("test_data/7-hires-quirks.ch8"));
+                    c.load_program(include_bytes!("test_data/7-hires-quirks.ch8")).unwrap();
                     c.cpu.memory.write(0x1ff, $mode_value); // Set chip8 mode
                     c.set_breakpoint(0x05d8);
 
                     for _ in 0..1000 {
-                        c.tick();
+                        c.tick().expect("Fault");
                         if c.halted {
                             break;
                         }
@@ -0,0 +1,301 @@
+use core::fmt;
+use serde::{de::Error, Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{
+    memory::InvalidMemorySize,
+    platform::Platform,
+    quirks::Quirks,
+    screenshot::Color,
+    timing::{Timing, CLOCK_SPEED_PER_IPF},
+    Chip8,
+};
+
+// The keypad on the left of a QWERTY keyboard, by SDL key name
+pub const DEFAULT_KEYMAP: [(&str, u8); 16] = [
+    ("1", 0x1),
+    ("2", 0x2),
+    ("3", 0x3),
+    ("4", 0xc),
+    ("Q", 0x4),
+    ("W", 0x5),
+    ("E", 0x6),
+    ("R", 0xd),
+    ("A", 0x7),
+    ("S", 0x8),
+    ("D", 0x9),
+    ("F", 0xe),
+    ("Z", 0xa),
+    ("X", 0x0),
+    ("C", 0xb),
+    ("V", 0xf),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    Read(String),
+    Parse(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(err) => write!(f, "Unable to read config: {}", err),
+            ConfigError::Parse(err) => write!(f, "Invalid config: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Quirks left out of the file keep the preset's value
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirkOverrides {
+    pub vf_reset: Option<bool>,
+    pub memory: Option<bool>,
+    pub display_wait: Option<bool>,
+    pub sprite_wrapping: Option<bool>,
+    pub hires_draw_flag: Option<bool>,
+    pub shifting: Option<bool>,
+    pub jumping: Option<bool>,
+    pub lores_half_scroll: Option<bool>,
+    pub lores_8x16_sprites: Option<bool>,
+    pub memory_increment_by_x: Option<bool>,
+    pub resolution_clears: Option<bool>,
+}
+
+impl QuirkOverrides {
+    pub fn apply(&self, quirks: &mut Quirks) {
+        let overrides = [
+            (self.vf_reset, &mut quirks.vf_reset),
+            (self.memory, &mut quirks.memory),
+            (self.display_wait, &mut quirks.display_wait),
+            (self.sprite_wrapping, &mut quirks.sprite_wrapping),
+            (self.hires_draw_flag, &mut quirks.hires_draw_flag),
+            (self.shifting, &mut quirks.shifting),
+            (self.jumping, &mut quirks.jumping),
+            (self.lores_half_scroll, &mut quirks.lores_half_scroll),
+            (self.lores_8x16_sprites, &mut quirks.lores_8x16_sprites),
+            (
+                self.memory_increment_by_x,
+                &mut quirks.memory_increment_by_x,
+            ),
+            (self.resolution_clears, &mut quirks.resolution_clears),
+        ];
+
+        for (value, quirk) in overrides {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+    }
+}
+
+// Emulator settings kept alongside a ROM, layered over a platform preset
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // One of `Platform::NAMES`, otherwise the rest applies to the current settings
+    #[serde(deserialize_with = "parsed")]
+    pub platform: Option<Platform>,
+    pub quirks: QuirkOverrides,
+    #[serde(deserialize_with = "parsed")]
+    pub timing: Option<Timing>,
+    pub clock_speed: Option<u32>,
+    // Instructions per frame, an easier way to give `clock_speed`
+    pub ipf: Option<u32>,
+    // "#rrggbb" colours, the background first
+    #[serde(deserialize_with = "palette")]
+    pub palette: Option<Vec<Color>>,
+    // Key names as the frontend knows them, mapped to keypad keys
+    pub keymap: HashMap<String, u8>,
+}
+
+impl Config {
+    pub fn from_toml(s: &str) -> Result<Config, ConfigError> {
+        toml::from_str::<Config>(s)
+            .map_err(|err| ConfigError::Parse(err.message().to_string()))?
+            .validate()
+    }
+
+    pub fn from_json(s: &str) -> Result<Config, ConfigError> {
+        serde_json::from_str::<Config>(s)
+            .map_err(|err| ConfigError::Parse(err.to_string()))?
+            .validate()
+    }
+
+    // Files ending in `.json` are JSON, anything else TOML
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::Read(format!("{}: {}", path.display(), err)))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Config::from_json(&s),
+            _ => Config::from_toml(&s),
+        }
+    }
+
+    fn validate(self) -> Result<Config, ConfigError> {
+        if let Some((name, key)) = self.keymap.iter().find(|(_, &key)| key > 0xf) {
+            return Err(ConfigError::Parse(format!(
+                "Key {} is mapped to {}, past the keypad",
+                name, key
+            )));
+        }
+        if let Some(ipf) = self
+            .ipf
+            .filter(|ipf| ipf.checked_mul(CLOCK_SPEED_PER_IPF).is_none())
+        {
+            return Err(ConfigError::Parse(format!(
+                "{} instructions per frame is too fast",
+                ipf
+            )));
+        }
+        if matches!(&self.palette, Some(palette) if palette.len() < 2) {
+            return Err(ConfigError::Parse(
+                "A palette needs at least two colours".to_string(),
+            ));
+        }
+
+        Ok(self)
+    }
+
+    // Call before `load_program`, a platform replaces memory
+    pub fn apply(&self, chip8: &mut Chip8) -> Result<(), InvalidMemorySize> {
+        if let Some(platform) = &self.platform {
+            chip8.set_platform(platform)?;
+        }
+
+        let cpu = &mut chip8.cpu;
+        self.quirks.apply(&mut cpu.quirks);
+        if let Some(timing) = self.timing {
+            cpu.timing = timing;
+        }
+        if let Some(clock_speed) = self.clock_speed {
+            cpu.clock_speed = clock_speed;
+        }
+        if let Some(clock_speed) = self
+            .ipf
+            .and_then(|ipf| ipf.checked_mul(CLOCK_SPEED_PER_IPF))
+        {
+            cpu.clock_speed = clock_speed;
+        }
+        Ok(())
+    }
+
+    // `DEFAULT_KEYMAP` with the file's keys on top
+    pub fn keymap(&self) -> HashMap<String, u8> {
+        let mut keymap: HashMap<String, u8> = DEFAULT_KEYMAP
+            .iter()
+            .map(|&(name, key)| (name.to_string(), key))
+            .collect();
+        keymap.extend(self.keymap.clone());
+        keymap
+    }
+}
+
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map(Some)
+        .map_err(D::Error::custom)
+}
+
+fn palette<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Color>>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| parse_color(s).ok_or_else(|| D::Error::custom(format!("Invalid colour {}", s))))
+        .collect::<Result<Vec<Color>, D::Error>>()
+        .map(Some)
+}
+
+pub(crate) fn parse_color(s: &str) -> Option<Color> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_layers_over_a_platform() {
+        let config = Config::from_toml(
+            r##"
+                platform = "schip-1.1"
+                ipf = 15
+                palette = ["#000000", "#ffcc00"]
+
+                [quirks]
+                shifting = false
+
+                [keymap]
+                Up = 0x5
+            "##,
+        )
+        .unwrap();
+
+        let mut chip8 = Chip8::new();
+        config.apply(&mut chip8).unwrap();
+
+        assert_eq!(
+            chip8.cpu.quirks,
+            Quirks {
+                shifting: false,
+                ..Quirks::superchip()
+            }
+        );
+        // ADD V0, 1 all the way through memory
+        chip8.load_program(&[0x70, 0x01].repeat(0x700)).unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.cpu.pc, 0x200 + 15 * 2, "15 instructions per frame");
+        assert_eq!(config.palette, Some(vec![[0, 0, 0], [0xff, 0xcc, 0]]));
+        assert_eq!(config.keymap().get("Up"), Some(&5));
+        assert_eq!(config.keymap().get("W"), Some(&5));
+    }
+
+    #[test]
+    fn config_reads_json() {
+        let config =
+            Config::from_json(r#"{ "timing": "vip", "quirks": { "vf_reset": false } }"#).unwrap();
+
+        let mut chip8 = Chip8::new();
+        config.apply(&mut chip8).unwrap();
+
+        assert_eq!(chip8.cpu.timing, Timing::Vip);
+        assert!(!chip8.cpu.quirks.vf_reset);
+        assert!(chip8.cpu.quirks.memory, "Other quirks are left alone");
+    }
+
+    #[test]
+    fn config_rejects_mistakes() {
+        for source in [
+            "platform = \"megachip\"",
+            "[quirks]\nclipping = true",
+            "palette = [\"#fff\", \"#000000\"]",
+            "palette = [\"#000000\"]",
+            "[keymap]\nQ = 16",
+            "speed = 10",
+            "ipf = 100000",
+        ] {
+            assert!(
+                matches!(Config::from_toml(source), Err(ConfigError::Parse(_))),
+                "{}",
+                source
+            );
+        }
+    }
+}
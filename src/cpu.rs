@@ -1,13 +1,27 @@
 use core::fmt;
 use log::{debug, info};
-use rand::Rng;
-use serde::Serialize;
-
-use super::{instruction::Instruction, memory::Memory, quirks::Quirks};
-
-#[derive(Default, Serialize)]
+use rand::{RngCore, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use super::{
+    audio::SQUARE_WAVE,
+    fault::{Fault, FaultPolicies, FaultPolicy},
+    instruction::{Instruction, OpcodeSet},
+    memory::{InvalidMemorySize, Memory, MEMORY_2K, MEMORY_64K},
+    platform::{Geometry, Platform},
+    quirks::Quirks,
+    timing::Timing,
+};
+
+#[derive(Serialize, Deserialize)]
 pub struct CPU {
     pub quirks: Quirks,
+    pub faults: FaultPolicies,
+    pub timing: Timing,
+    // Instructions beyond this extension are invalid
+    pub opcodes: OpcodeSet,
     pub clock_speed: u32,
 
     pub running: bool,
@@ -18,6 +32,12 @@ pub struct CPU {
 
     pub pc: u16,
     stack: Vec<u16>,
+    pub stack_depth: usize,
+    // Set by instructions that fault while executing, reported by `step`
+    #[serde(skip)]
+    fault: Option<Fault>,
+    // Set by DRW with the display wait quirk, nothing more runs until the next frame
+    pub vblank_wait: bool,
 
     vx: [u8; 16],
     dt: u8,
@@ -26,9 +46,26 @@ pub struct CPU {
 
     save: [u8; 8],
 
+    // Seeded generator for RND, unless the caller supplies their own source
+    rng: Pcg32,
+    #[serde(skip)]
+    custom_rng: Option<Box<dyn RngCore>>,
+
+    pub plane: u8,
+    pub pattern: [u8; 16],
+    pub pitch: u8,
+
+    // The sizes LORES and HIRES switch to
+    pub geometry: Geometry,
     pub width: usize,
     pub height: usize,
-    pub vram: Vec<bool>,
+    // One bit per plane, so each pixel reads back as a 2-bit colour index
+    pub vram: Vec<u8>,
+    // Display changes since the frontend last cleared them
+    #[serde(skip)]
+    dirty_rows: Option<(usize, usize)>,
+    #[serde(skip)]
+    presented: bool,
 }
 
 impl fmt::Display for CPU {
@@ -45,10 +82,19 @@ impl fmt::Display for CPU {
     }
 }
 
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CPU {
     pub fn new() -> CPU {
         CPU {
             quirks: Quirks::default(),
+            faults: FaultPolicies::default(),
+            timing: Timing::default(),
+            opcodes: OpcodeSet::XoChip,
             clock_speed: 1_000_000, // MHz
 
             running: true,
@@ -64,12 +110,105 @@ impl CPU {
             dt: 0,
             st: 0,
             stack: vec![],
+            stack_depth: 16,
+            fault: None,
+            vblank_wait: false,
 
             save: [0; 8],
 
+            rng: Pcg32::from_entropy(),
+            custom_rng: None,
+
+            plane: 1,
+            pattern: SQUARE_WAVE,
+            pitch: 64,
+
+            geometry: Geometry::default(),
             width: 64,
             height: 32,
-            vram: vec![false; 64 * 32],
+            vram: vec![0; 64 * 32],
+            // Nothing has been shown yet
+            dirty_rows: Some((0, 32)),
+            presented: false,
+        }
+    }
+
+    // Behave like `platform`, clearing memory so call it before loading a program
+    pub fn set_platform(&mut self, platform: &Platform) -> Result<(), InvalidMemorySize> {
+        self.memory = Memory::with_size(platform.memory_size)?;
+        self.memory.load_font(platform.font);
+        self.quirks = platform.quirks.clone();
+        self.timing = platform.timing;
+        self.clock_speed = platform.clock_speed;
+        self.stack_depth = platform.stack_depth;
+        self.opcodes = platform.opcodes;
+        self.geometry = platform.geometry;
+        self.set_resolution(false);
+        self.vram.fill(0);
+        Ok(())
+    }
+
+    // Reseed RND so runs are reproducible, replacing any custom source
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Pcg32::seed_from_u64(seed);
+        self.custom_rng = None;
+    }
+
+    // What's wrong with a machine decoded from a save state, before it panics somewhere
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        for (width, height) in [self.geometry.lores, self.geometry.hires] {
+            if !matches!((width, height), (64, 32) | (128, 64)) {
+                return Err(format!("Display mode is {}x{}", width, height));
+            }
+        }
+        let (width, height) = self.resolution();
+        if (self.width, self.height) != (width, height) {
+            return Err(format!(
+                "Display is {}x{} in a {}x{} mode",
+                self.width, self.height, width, height
+            ));
+        }
+        if self.vram.len() != self.width * self.height {
+            return Err(format!(
+                "{} pixels for a {}x{} display",
+                self.vram.len(),
+                self.width,
+                self.height
+            ));
+        }
+        if !(MEMORY_2K..=MEMORY_64K).contains(&self.memory.size()) {
+            return Err(format!("{} bytes of memory", self.memory.size()));
+        }
+        // Only halting keeps the stack within its depth
+        if self.stack.len() > self.stack_depth && self.faults.stack_overflow == FaultPolicy::Halt {
+            return Err(format!(
+                "{} stack entries with a depth of {}",
+                self.stack.len(),
+                self.stack_depth
+            ));
+        }
+        if self.plane > 3 {
+            return Err(format!("Plane mask {}", self.plane));
+        }
+        Ok(())
+    }
+
+    pub fn set_rng(&mut self, rng: impl RngCore + 'static) {
+        self.custom_rng = Some(Box::new(rng));
+    }
+
+    // Replace the machine state with a restored one, keeping any custom RNG source
+    pub fn restore(&mut self, state: CPU) {
+        let custom_rng = self.custom_rng.take();
+        *self = state;
+        self.custom_rng = custom_rng;
+        self.mark_dirty(0..self.height);
+    }
+
+    fn random_byte(&mut self) -> u8 {
+        match &mut self.custom_rng {
+            Some(rng) => rng.next_u32() as u8,
+            None => self.rng.next_u32() as u8,
         }
     }
 
@@ -89,30 +228,126 @@ impl CPU {
         self.keys[(key & 0xf) as usize] = false;
     }
 
+    // `execute` runs after PC has moved past the instruction, which is always 2 bytes here
     fn push(&mut self, v: u16) {
+        if self.stack.len() >= self.stack_depth && self.faults.stack_overflow == FaultPolicy::Halt {
+            self.fault = Some(Fault::StackOverflow {
+                addr: self.pc.wrapping_sub(2),
+            });
+            return;
+        }
+
         self.stack.push(v);
     }
 
     fn pop(&mut self) -> u16 {
-        self.stack.pop().unwrap_or(0)
+        match (self.stack.pop(), self.faults.stack_underflow) {
+            (Some(v), _) => v,
+            (None, FaultPolicy::Ignore) => self.pc,
+            (None, FaultPolicy::Halt) => {
+                self.fault = Some(Fault::StackUnderflow {
+                    addr: self.pc.wrapping_sub(2),
+                });
+                self.pc
+            }
+            (None, FaultPolicy::Emulate) => 0,
+        }
     }
 
-    pub fn step(&mut self) -> u32 {
-        let op: u16 =
-            ((self.memory.read(self.pc) as u16) << 8) + (self.memory.read(self.pc + 1) as u16);
+    fn check_pc(&mut self) -> Result<(), Fault> {
+        let addr = self.pc;
+        let out_of_bounds = (addr as usize) < 0x200 || (addr as usize) + 1 >= self.memory.size();
 
-        let inst = Instruction::parse(op);
+        if out_of_bounds && self.faults.pc_out_of_bounds == FaultPolicy::Halt {
+            return Err(Fault::PcOutOfBounds { addr });
+        }
+        if addr & 1 == 1 && self.faults.misaligned_pc == FaultPolicy::Halt {
+            return Err(Fault::MisalignedPc { addr });
+        }
 
-        debug!("{} {:?}", self, inst);
+        // Memory reads already wrap, keep PC in range to match
+        self.pc = (addr as usize % self.memory.size()) as u16;
 
-        self.pc += 2;
+        Ok(())
+    }
+
+    fn read_word(&self, addr: u16) -> u16 {
+        ((self.memory.read(addr) as u16) << 8) + (self.memory.read(addr.wrapping_add(1)) as u16)
+    }
+
+    // Skip the next instruction, which may be a 4 byte `F000 NNNN`
+    fn skip(&mut self) {
+        self.pc = self
+            .pc
+            .wrapping_add(Instruction::size_of(self.read_word(self.pc)));
+    }
+
+    pub fn step(&mut self) -> Result<u32, Fault> {
+        self.check_pc()?;
+
+        let addr = self.pc;
+        let op = self.read_word(self.pc);
+        let next = self.read_word(self.pc.wrapping_add(2));
+
+        let inst = Instruction::parse(op, next);
+
+        debug!("{} {}", self, inst);
+
+        if let Instruction::Unknown(op) = inst {
+            // Leave PC on the bad opcode so the caller can inspect it
+            return Err(Fault::InvalidOpcode { op, addr: self.pc });
+        }
+        if inst.opcode_set() > self.opcodes {
+            return Err(Fault::InvalidOpcode { op, addr: self.pc });
+        }
+
+        self.pc = self.pc.wrapping_add(inst.size());
 
         let cycles = self.execute(inst);
 
-        match (inst, self.quirks.display_wait) {
-            (Instruction::DRW_Vx_Vy_n(_, _, _), true) => self.clock_speed / 6000,
-            _ => cycles,
+        if let Some(fault) = self.fault.take() {
+            self.pc = addr;
+            return Err(fault);
         }
+
+        if let (Instruction::DRW_Vx_Vy_n(_, _, _), true) = (inst, self.quirks.display_wait) {
+            self.vblank_wait = true;
+        }
+
+        Ok(cycles)
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.vx
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    // The display as rows of blocks and spaces, any lit plane counts as on
+    pub fn display_text(&self) -> String {
+        self.vram
+            .chunks_exact(self.width)
+            .map(|row| {
+                row.iter()
+                    .map(|v| if *v != 0 { '█' } else { ' ' })
+                    .collect()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 
     pub fn tick_timers(&mut self) {
@@ -126,6 +361,8 @@ impl CPU {
     }
 
     pub fn execute(&mut self, inst: Instruction) -> u32 {
+        let (vx, pc) = (self.vx, self.pc);
+
         #[allow(unused_variables)]
         match inst {
             Instruction::SYS_addr(_) => {}
@@ -167,27 +404,28 @@ impl CPU {
 
             Instruction::LD_iI_Vx(x) => {
                 for i in 0..(x + 1) {
-                    self.memory.write(self.i + (i as u16), self.vx[i as usize])
+                    self.memory
+                        .write(self.i.wrapping_add(i as u16), self.vx[i as usize])
                 }
                 if self.quirks.memory {
-                    self.i += (x as u16) + 1
+                    self.i = self.i.wrapping_add(self.memory_increment(x))
                 }
             }
             Instruction::LD_Vx_iI(x) => {
                 for i in 0..(x + 1) {
-                    self.vx[i as usize] = self.memory.read(self.i + (i as u16))
+                    self.vx[i as usize] = self.memory.read(self.i.wrapping_add(i as u16))
                 }
                 if self.quirks.memory {
-                    self.i += (x as u16) + 1
+                    self.i = self.i.wrapping_add(self.memory_increment(x))
                 }
             }
 
             Instruction::LD_B_Vx(x) => {
+                self.memory.write(self.i, (self.vx[x as usize] / 100) % 10);
                 self.memory
-                    .write(self.i + 0, (self.vx[x as usize] / 100) % 10);
+                    .write(self.i.wrapping_add(1), (self.vx[x as usize] / 10) % 10);
                 self.memory
-                    .write(self.i + 1, (self.vx[x as usize] / 10) % 10);
-                self.memory.write(self.i + 2, (self.vx[x as usize]) % 10);
+                    .write(self.i.wrapping_add(2), (self.vx[x as usize]) % 10);
             }
 
             Instruction::ADD_Vx_kk(x, kk) => {
@@ -195,9 +433,11 @@ impl CPU {
                 self.vx[x as usize] = result;
             }
             Instruction::ADD_I_Vx(x) => {
-                self.i += self.vx[x as usize] as u16;
-                self.vx[0xf] = if self.i > 0x0fff { 1 } else { 0 };
-                self.i &= 0x0fff;
+                // I is only clamped to 12 bits when there is no more memory to reach
+                let limit = self.memory.size().max(0x1000);
+                let i = self.i as usize + self.vx[x as usize] as usize;
+                self.vx[0xf] = if i >= limit { 1 } else { 0 };
+                self.i = (i % limit) as u16;
             }
             Instruction::ADD_Vx_Vy(x, y) => {
                 let (result, overflow) = self.vx[x as usize].overflowing_add(self.vx[y as usize]);
@@ -208,23 +448,23 @@ impl CPU {
 
             Instruction::SE_Vx_kk(x, kk) => {
                 if self.vx[x as usize] == kk {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             Instruction::SNE_Vx_kk(x, kk) => {
                 if self.vx[x as usize] != kk {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
             Instruction::SE_Vx_Vy(x, y) => {
                 if self.vx[x as usize] == self.vx[y as usize] {
-                    self.pc += 2
+                    self.skip();
                 }
             }
             Instruction::SNE_Vx_Vy(x, y) => {
                 if self.vx[x as usize] != self.vx[y as usize] {
-                    self.pc += 2
+                    self.skip();
                 }
             }
 
@@ -281,22 +521,22 @@ impl CPU {
                 if let Some(res) = self.keys.iter().position(|v| *v) {
                     self.vx[x as usize] = res as u8;
                 } else {
-                    self.pc -= 2;
+                    self.pc = self.pc.wrapping_sub(2);
                 }
             }
             Instruction::SKP_Vx(x) => {
                 if self.keys[(self.vx[x as usize] & 0xf) as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             Instruction::SKNP_Vx(x) => {
                 if !self.keys[(self.vx[x as usize] & 0xf) as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
             Instruction::RND_Vx_kk(x, kk) => {
-                self.vx[x as usize] = rand::thread_rng().gen_range(0..=kk);
+                self.vx[x as usize] = self.random_byte() & kk;
             }
 
             Instruction::LD_F_Vx(x) => {
@@ -307,147 +547,65 @@ impl CPU {
             }
 
             Instruction::CLS => {
-                self.vram.fill(false);
-            }
-
-            Instruction::LORES => {
-                self.hires = false;
-                self.width = 64;
-                self.height = 32;
-                self.vram.resize(64 * 32, false)
-            }
-
-            Instruction::HIRES => {
-                self.hires = true;
-                self.width = 128;
-                self.height = 64;
-                self.vram.resize(128 * 64, false)
-            }
-
-            Instruction::SCD_n(n) => {
-                // Scroll down, need to verify expected wrapping behavior
-                self.vram.copy_within(
-                    // First (h-n) rows
-                    0..((self.height - n as usize) * self.width),
-                    (n as usize) * self.width,
-                );
-                self.vram[0..((n as usize) * self.width)].fill(false);
-            }
-
-            Instruction::SCR => {
-                // for i in 1..self.height {
-                //     self.vram.copy_within(0..(self.width - 4), 4)
-                // }
-                // For each row, offset right by 4 pixels
-                for row in self.vram.chunks_mut(self.width) {
-                    row.copy_within(..(self.width - 4), 4);
-                    row[..4].fill(false);
+                self.presented = true;
+                if self.vram.iter().any(|pixel| pixel & self.plane != 0) {
+                    for pixel in self.vram.iter_mut() {
+                        *pixel &= !self.plane;
+                    }
+                    self.mark_dirty(0..self.height);
                 }
             }
 
-            Instruction::SCL => {
-                // For each row, offset left by 8 pixels
-                for row in self.vram.chunks_mut(self.width) {
-                    row.copy_within(4.., 0);
-                    row[(self.width - 4)..].fill(false);
+            Instruction::LORES | Instruction::HIRES => {
+                self.set_resolution(inst == Instruction::HIRES);
+                if self.quirks.resolution_clears {
+                    self.vram.fill(0);
                 }
             }
 
+            Instruction::SCD_n(n) => self.scroll(0, n as isize),
+            Instruction::SCU_n(n) => self.scroll(0, -(n as isize)),
+            Instruction::SCR => self.scroll(4, 0),
+            Instruction::SCL => self.scroll(-4, 0),
+
             Instruction::DRW_Vx_Vy_n(vx, vy, n) => {
+                self.presented = true;
                 let mut x: usize = self.vx[vx as usize] as usize;
                 let mut y: usize = self.vx[vy as usize] as usize;
 
                 if x >= self.width {
-                    x = x % self.width;
+                    x %= self.width;
                 }
                 if y >= self.height {
-                    y = y % self.height;
+                    y %= self.height;
                 }
 
-                self.vx[0xf] = 0;
-
-                if n == 0 {
-                    // 16x16 sprite!
-                    for row in 0..16 {
-                        if (y + row) >= self.height {
-                            self.vx[0xf] += 1;
-
-                            if !self.quirks.sprite_wrapping {
-                                break;
-                            }
-                        }
-
-                        let row_offset = if (y + row) >= self.height {
-                            self.width * ((y + row) as usize - self.height)
-                        } else {
-                            self.width * ((y + row) as usize)
-                        };
-
-                        let bits = (self.memory.read(self.i + (row as u16) * 2) as u16) << 8
-                            | (self.memory.read(self.i + (row as u16) * 2 + 1) as u16);
-
-                        let mut clobber = false;
-                        for col in 0..16 {
-                            if (x + col) >= self.width && !self.quirks.sprite_wrapping {
-                                break;
-                            }
-
-                            let offset = if (x + col) >= self.width {
-                                row_offset + x + col - self.width
-                            } else {
-                                row_offset + x + col
-                            };
-
-                            if bits & (1 << (15 - col)) > 0 {
-                                if self.vram[offset] {
-                                    clobber = true;
-                                }
-
-                                self.vram[offset] = !self.vram[offset];
-                            }
-                        }
-
-                        if clobber {
-                            self.vx[0xf] += 1;
-                        }
-                    }
-                } else {
-                    for row in 0..(n as usize) {
-                        if (y + row) >= self.height {
-                            if !self.quirks.sprite_wrapping {
-                                break;
-                            }
-                        }
-
-                        let row_offset = if (y + row) >= self.height {
-                            self.width * ((y + row) as usize - self.height)
-                        } else {
-                            self.width * ((y + row) as usize)
-                        };
-
-                        let bits = self.memory.read(self.i + (row as u16));
-
-                        for col in 0..8 {
-                            if (x + col) >= self.width && !self.quirks.sprite_wrapping {
-                                break;
-                            }
-
-                            let offset = if (x + col) >= self.width {
-                                row_offset + x + col - self.width
-                            } else {
-                                row_offset + x + col
-                            };
-
-                            if bits & (1 << (7 - col)) > 0 {
-                                if self.vram[offset] {
-                                    self.vx[0xf] = 1;
-                                }
-
-                                self.vram[offset] = !self.vram[offset];
-                            }
-                        }
+                // Each selected plane consumes its own sprite data, starting at I
+                let (mut collided, mut clipped) = (0, 0);
+                let mut addr = self.i;
+                for mask in [0b01, 0b10] {
+                    if self.plane & mask == 0 {
+                        continue;
                     }
+
+                    let (rows, bytes) = if n != 0 {
+                        (self.draw_8xn(x, y, n, addr, mask), n)
+                    } else if !self.hires && self.quirks.lores_8x16_sprites {
+                        (self.draw_8xn(x, y, 16, addr, mask), 16)
+                    } else {
+                        (self.draw_16x16(x, y, addr, mask), 32)
+                    };
+                    collided += rows.0;
+                    clipped += rows.1;
+                    addr = addr.wrapping_add(bytes as u16);
                 }
+
+                // SCHIP counts the rows that collided or fell off the bottom in hires
+                self.vx[0xf] = if self.hires && self.quirks.hires_draw_flag {
+                    collided + clipped
+                } else {
+                    (collided > 0) as u8
+                };
             }
 
             Instruction::SAVE_Vx(x) => {
@@ -461,16 +619,224 @@ impl CPU {
                     self.vx[i as usize] = self.save[i as usize];
                 }
             }
+
+            Instruction::LD_iI_Vx_Vy(x, y) => {
+                // Registers are stored in order from x to y, which may be descending
+                for (offset, reg) in register_range(x, y).enumerate() {
+                    self.memory
+                        .write(self.i.wrapping_add(offset as u16), self.vx[reg as usize]);
+                }
+            }
+            Instruction::LD_Vx_Vy_iI(x, y) => {
+                for (offset, reg) in register_range(x, y).enumerate() {
+                    self.vx[reg as usize] = self.memory.read(self.i.wrapping_add(offset as u16));
+                }
+            }
+
+            Instruction::LD_I_long(addr) => self.i = addr,
+
+            Instruction::PLANE_n(n) => self.plane = n & 0x3,
+
+            Instruction::AUDIO => {
+                for i in 0..16 {
+                    self.pattern[i] = self.memory.read(self.i.wrapping_add(i as u16));
+                }
+            }
+
+            Instruction::PITCH_Vx(x) => self.pitch = self.vx[x as usize],
+
+            Instruction::Unknown(_) => {}
+        }
+
+        self.timing.cycles(inst, &vx, self.pc != pc)
+    }
+
+    // Shift the selected planes by (dx, dy), filling the exposed pixels with 0
+    // Width and height of the current display mode
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            self.geometry.hires
+        } else {
+            self.geometry.lores
         }
+    }
+
+    fn set_resolution(&mut self, hires: bool) {
+        self.hires = hires;
+        (self.width, self.height) = self.resolution();
+        self.vram.resize(self.width * self.height, 0);
+        self.mark_dirty(0..self.height);
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (dx, dy) = if !self.hires && self.quirks.lores_half_scroll {
+            (dx / 2, dy / 2)
+        } else {
+            (dx, dy)
+        };
+        let (width, height) = (self.width as isize, self.height as isize);
+        let source = self.vram.clone();
+
+        self.presented = true;
+        self.mark_dirty(0..self.height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let bits = if (0..width).contains(&sx) && (0..height).contains(&sy) {
+                    source[(sy * width + sx) as usize] & self.plane
+                } else {
+                    0
+                };
+
+                let pixel = &mut self.vram[(y * width + x) as usize];
+                *pixel = (*pixel & !self.plane) | bits;
+            }
+        }
+    }
+
+    // Toggle a pixel in the plane `mask`, returning true if it was switched off
+    fn flip(&mut self, offset: usize, mask: u8) -> bool {
+        let collision = self.vram[offset] & mask != 0;
+        self.vram[offset] ^= mask;
+
+        let row = offset / self.width;
+        self.mark_dirty(row..row + 1);
+
+        collision
+    }
+
+    fn mark_dirty(&mut self, rows: Range<usize>) {
+        self.dirty_rows = Some(match self.dirty_rows {
+            Some((start, end)) => (start.min(rows.start), end.max(rows.end)),
+            None => (rows.start, rows.end),
+        });
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_rows.is_some()
+    }
+
+    // Rows of `vram` changed since `clear_dirty`
+    pub fn dirty_rows(&self) -> Option<Range<usize>> {
+        self.dirty_rows.map(|(start, end)| start..end)
+    }
+
+    // Whether the ROM drew, cleared or scrolled since `clear_dirty`, even if no pixel changed
+    pub fn frame_presented(&self) -> bool {
+        self.presented
+    }
 
-        8
+    // Called once the display has been shown
+    pub fn clear_dirty(&mut self) {
+        self.dirty_rows = None;
+        self.presented = false;
+    }
+
+    // FX55 and FX65 step I past the registers, or onto the last one
+    fn memory_increment(&self, x: u8) -> u16 {
+        if self.quirks.memory_increment_by_x {
+            x as u16
+        } else {
+            x as u16 + 1
+        }
+    }
+
+    // Sprites return the number of rows that collided and that were clipped at the bottom
+    fn draw_16x16(&mut self, x: usize, y: usize, addr: u16, mask: u8) -> (u8, u8) {
+        let (mut collided, mut clipped) = (0, 0);
+        for row in 0..16 {
+            if (y + row) >= self.height && !self.quirks.sprite_wrapping {
+                clipped = (16 - row) as u8;
+                break;
+            }
+
+            let row_offset = if (y + row) >= self.height {
+                self.width * ((y + row) - self.height)
+            } else {
+                self.width * (y + row)
+            };
+
+            let bits = self.read_word(addr.wrapping_add((row as u16) * 2));
+
+            let mut clobber = false;
+            for col in 0..16 {
+                if (x + col) >= self.width && !self.quirks.sprite_wrapping {
+                    break;
+                }
+
+                let offset = if (x + col) >= self.width {
+                    row_offset + x + col - self.width
+                } else {
+                    row_offset + x + col
+                };
+
+                if bits & (1 << (15 - col)) > 0 && self.flip(offset, mask) {
+                    clobber = true;
+                }
+            }
+
+            if clobber {
+                collided += 1;
+            }
+        }
+        (collided, clipped)
+    }
+
+    fn draw_8xn(&mut self, x: usize, y: usize, n: u8, addr: u16, mask: u8) -> (u8, u8) {
+        let (mut collided, mut clipped) = (0, 0);
+        for row in 0..(n as usize) {
+            if (y + row) >= self.height && !self.quirks.sprite_wrapping {
+                clipped = n - row as u8;
+                break;
+            }
+
+            let row_offset = if (y + row) >= self.height {
+                self.width * ((y + row) - self.height)
+            } else {
+                self.width * (y + row)
+            };
+
+            let bits = self.memory.read(addr.wrapping_add(row as u16));
+
+            let mut clobber = false;
+            for col in 0..8 {
+                if (x + col) >= self.width && !self.quirks.sprite_wrapping {
+                    break;
+                }
+
+                let offset = if (x + col) >= self.width {
+                    row_offset + x + col - self.width
+                } else {
+                    row_offset + x + col
+                };
+
+                if bits & (1 << (7 - col)) > 0 && self.flip(offset, mask) {
+                    clobber = true;
+                }
+            }
+
+            if clobber {
+                collided += 1;
+            }
+        }
+        (collided, clipped)
     }
 }
 
+// X to Y inclusive, counting down when Y is lower
+fn register_range(x: u8, y: u8) -> impl Iterator<Item = u8> {
+    (0..=x.abs_diff(y)).map(move |i| if x <= y { x + i } else { x - i })
+}
+
 #[allow(non_snake_case)]
 #[cfg(test)]
 mod tests {
-    use crate::instruction::Instruction;
+    use crate::{
+        fault::{Fault, FaultPolicies, FaultPolicy},
+        instruction::Instruction,
+        memory::{Memory, MEMORY_64K},
+    };
 
     use super::CPU;
 
@@ -493,9 +859,9 @@ mod tests {
     pub fn test_CLS() {
         let mut cpu = CPU::new();
         let inst = Instruction::CLS;
-        cpu.vram.fill(true);
+        cpu.vram.fill(1);
         cpu.execute(inst);
-        assert_eq!(cpu.vram[0], false, "VRAM should be filled with `false`");
+        assert_eq!(cpu.vram[0], 0, "VRAM should be filled with `0`");
     }
 
     #[test]
@@ -518,7 +884,7 @@ mod tests {
         assert_eq!(cpu.pc, 0x444, "PC should be set to the jump address");
         assert_eq!(
             cpu.stack,
-            vec![],
+            Vec::<u16>::new(),
             "The previous address should not be on the stack"
         );
     }
@@ -628,6 +994,44 @@ mod tests {
     //     let inst = Instruction::ADD_Vx_Vy(u8, u8);
     //     cpu.execute(inst);
     // }
+    #[test]
+    pub fn test_ADD_I_Vx() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x0ffe;
+        cpu.vx[1] = 4;
+
+        cpu.execute(Instruction::ADD_I_Vx(1));
+        assert_eq!(cpu.i, 0x0002, "I should wrap at 12 bits");
+        assert_eq!(cpu.vx[0xf], 1);
+    }
+
+    #[test]
+    pub fn test_ADD_I_Vx_64k() {
+        let mut cpu = CPU::new();
+        cpu.memory = Memory::with_size(MEMORY_64K).unwrap();
+        cpu.i = 0x0ffe;
+        cpu.vx[1] = 4;
+
+        cpu.execute(Instruction::ADD_I_Vx(1));
+        assert_eq!(cpu.i, 0x1002, "I should not be clamped with 64K of memory");
+        assert_eq!(cpu.vx[0xf], 0);
+    }
+
+    #[test]
+    pub fn test_LD_Vx_K_64k_wrap() {
+        let mut cpu = CPU::new();
+        cpu.memory = Memory::with_size(MEMORY_64K).unwrap();
+        cpu.memory.write(0xfffe, 0xf0);
+        cpu.memory.write(0xffff, 0x0a);
+        cpu.pc = 0xfffe;
+
+        cpu.step().unwrap();
+        assert_eq!(
+            cpu.pc, 0xfffe,
+            "Waiting for a key should stay on the last word"
+        );
+    }
+
     #[test]
     pub fn test_SUB_Vx_Vy() {
         let mut cpu = CPU::new();
@@ -757,12 +1161,38 @@ mod tests {
     //     let inst = Instruction::LD_I_addr(u16);
     //     cpu.execute(inst);
     // }
-    // #[test]
-    // pub fn test_RND_Vx_kk() {
-    //     let mut cpu = CPU::new();
-    //     let inst = Instruction::RND_Vx_kk(u8, u8);
-    //     cpu.execute(inst);
-    // }
+    #[test]
+    pub fn test_RND_Vx_kk() {
+        let run = |seed| {
+            let mut cpu = CPU::new();
+            cpu.seed(seed);
+            (0..16)
+                .map(|x| {
+                    cpu.execute(Instruction::RND_Vx_kk(x, 0x0f));
+                    cpu.vx[x as usize]
+                })
+                .collect::<Vec<u8>>()
+        };
+
+        assert_eq!(run(1), run(1), "The same seed should give the same bytes");
+        assert_ne!(run(1), run(2));
+        assert!(run(1).iter().all(|v| v & 0xf0 == 0), "RND masks with kk");
+    }
+
+    #[test]
+    pub fn test_RND_Vx_kk_custom_rng() {
+        let mut cpu = CPU::new();
+        cpu.set_rng(rand::rngs::mock::StepRng::new(0xabcd, 0));
+
+        cpu.execute(Instruction::RND_Vx_kk(0, 0xff));
+        assert_eq!(cpu.vx[0], 0xcd);
+        cpu.execute(Instruction::RND_Vx_kk(0, 0x0f));
+        assert_eq!(cpu.vx[0], 0x0d);
+
+        cpu.seed(1);
+        cpu.execute(Instruction::RND_Vx_kk(0, 0xff));
+        assert_ne!(cpu.vx[0], 0xcd, "Seeding replaces a custom source");
+    }
     // #[test]
     // pub fn test_SKP_Vx() {
     //     let mut cpu = CPU::new();
@@ -844,8 +1274,8 @@ mod tests {
 
         cpu.width = 10;
         cpu.height = 10;
-        cpu.vram = vec![false; 100];
-        cpu.vram.fill(true);
+        cpu.vram = vec![0; 100];
+        cpu.vram.fill(1);
 
         cpu.execute(Instruction::SCD_n(2));
 
@@ -853,16 +1283,16 @@ mod tests {
         assert_eq!(
             chunks,
             [
-                [false, false, false, false, false, false, false, false, false, false],
-                [false, false, false, false, false, false, false, false, false, false],
-                [true, true, true, true, true, true, true, true, true, true],
-                [true, true, true, true, true, true, true, true, true, true],
-                [true, true, true, true, true, true, true, true, true, true],
-                [true, true, true, true, true, true, true, true, true, true],
-                [true, true, true, true, true, true, true, true, true, true],
-                [true, true, true, true, true, true, true, true, true, true],
-                [true, true, true, true, true, true, true, true, true, true],
-                [true, true, true, true, true, true, true, true, true, true],
+                [0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
             ]
         );
     }
@@ -873,23 +1303,23 @@ mod tests {
 
         cpu.width = 7;
         cpu.height = 7;
-        cpu.vram = vec![false; 7 * 7];
+        cpu.vram = vec![0; 7 * 7];
 
         for i in 0..(cpu.width * cpu.height) {
-            cpu.vram[i] = if i % 2 == 0 { true } else { false };
+            cpu.vram[i] = if i % 2 == 0 { 1 } else { 0 };
         }
 
         let (chunks, _) = cpu.vram.as_chunks::<7>();
         assert_eq!(
             chunks,
             [
-                [true, false, true, false, true, false, true],
-                [false, true, false, true, false, true, false],
-                [true, false, true, false, true, false, true],
-                [false, true, false, true, false, true, false],
-                [true, false, true, false, true, false, true],
-                [false, true, false, true, false, true, false],
-                [true, false, true, false, true, false, true]
+                [1, 0, 1, 0, 1, 0, 1],
+                [0, 1, 0, 1, 0, 1, 0],
+                [1, 0, 1, 0, 1, 0, 1],
+                [0, 1, 0, 1, 0, 1, 0],
+                [1, 0, 1, 0, 1, 0, 1],
+                [0, 1, 0, 1, 0, 1, 0],
+                [1, 0, 1, 0, 1, 0, 1]
             ]
         );
 
@@ -898,13 +1328,13 @@ mod tests {
         assert_eq!(
             chunks,
             [
-                [false, false, false, false, true, false, true],
-                [false, false, false, false, false, true, false],
-                [false, false, false, false, true, false, true],
-                [false, false, false, false, false, true, false],
-                [false, false, false, false, true, false, true],
-                [false, false, false, false, false, true, false],
-                [false, false, false, false, true, false, true]
+                [0, 0, 0, 0, 1, 0, 1],
+                [0, 0, 0, 0, 0, 1, 0],
+                [0, 0, 0, 0, 1, 0, 1],
+                [0, 0, 0, 0, 0, 1, 0],
+                [0, 0, 0, 0, 1, 0, 1],
+                [0, 0, 0, 0, 0, 1, 0],
+                [0, 0, 0, 0, 1, 0, 1]
             ]
         );
     }
@@ -915,23 +1345,23 @@ mod tests {
 
         cpu.width = 7;
         cpu.height = 7;
-        cpu.vram = vec![false; 7 * 7];
+        cpu.vram = vec![0; 7 * 7];
 
         for i in 0..(cpu.width * cpu.height) {
-            cpu.vram[i] = if i % 2 == 0 { true } else { false };
+            cpu.vram[i] = if i % 2 == 0 { 1 } else { 0 };
         }
 
         let (chunks, _) = cpu.vram.as_chunks::<7>();
         assert_eq!(
             chunks,
             [
-                [true, false, true, false, true, false, true],
-                [false, true, false, true, false, true, false],
-                [true, false, true, false, true, false, true],
-                [false, true, false, true, false, true, false],
-                [true, false, true, false, true, false, true],
-                [false, true, false, true, false, true, false],
-                [true, false, true, false, true, false, true]
+                [1, 0, 1, 0, 1, 0, 1],
+                [0, 1, 0, 1, 0, 1, 0],
+                [1, 0, 1, 0, 1, 0, 1],
+                [0, 1, 0, 1, 0, 1, 0],
+                [1, 0, 1, 0, 1, 0, 1],
+                [0, 1, 0, 1, 0, 1, 0],
+                [1, 0, 1, 0, 1, 0, 1]
             ]
         );
 
@@ -941,13 +1371,13 @@ mod tests {
         assert_eq!(
             chunks,
             [
-                [true, false, true, false, false, false, false],
-                [false, true, false, false, false, false, false],
-                [true, false, true, false, false, false, false],
-                [false, true, false, false, false, false, false],
-                [true, false, true, false, false, false, false],
-                [false, true, false, false, false, false, false],
-                [true, false, true, false, false, false, false]
+                [1, 0, 1, 0, 0, 0, 0],
+                [0, 1, 0, 0, 0, 0, 0],
+                [1, 0, 1, 0, 0, 0, 0],
+                [0, 1, 0, 0, 0, 0, 0],
+                [1, 0, 1, 0, 0, 0, 0],
+                [0, 1, 0, 0, 0, 0, 0],
+                [1, 0, 1, 0, 0, 0, 0]
             ]
         );
     }
@@ -997,4 +1427,332 @@ mod tests {
         cpu.execute(Instruction::LOAD_Vx(2));
         assert_eq!(cpu.vx, [0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
     }
+
+    #[test]
+    pub fn test_Unknown() {
+        let mut cpu = CPU::new();
+        cpu.memory.load_program(&[0x00, 0xe0, 0xff, 0xff]).unwrap();
+
+        cpu.step().unwrap();
+        assert_eq!(
+            cpu.step(),
+            Err(Fault::InvalidOpcode {
+                op: 0xffff,
+                addr: 0x202
+            })
+        );
+        assert_eq!(cpu.pc, 0x202, "PC should stay on the invalid opcode");
+    }
+
+    #[test]
+    pub fn test_stack_underflow() {
+        let mut cpu = CPU::new();
+        cpu.memory.load_program(&[0x00, 0xee]).unwrap();
+
+        cpu.faults.stack_underflow = FaultPolicy::Emulate;
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x000, "RET should return to $0000");
+
+        cpu.pc = 0x200;
+        cpu.faults.stack_underflow = FaultPolicy::Ignore;
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x202, "RET should be skipped");
+
+        cpu.pc = 0x200;
+        cpu.faults.stack_underflow = FaultPolicy::Halt;
+        assert_eq!(cpu.step(), Err(Fault::StackUnderflow { addr: 0x200 }));
+        assert_eq!(cpu.pc, 0x200, "PC should stay on the RET");
+    }
+
+    #[test]
+    pub fn test_stack_overflow() {
+        let mut cpu = CPU::new();
+        cpu.stack_depth = 2;
+        // 0x200: CALL 0x202, 0x202: CALL 0x204, 0x204: CALL 0x206
+        cpu.memory
+            .load_program(&[0x22, 0x02, 0x22, 0x04, 0x22, 0x06])
+            .unwrap();
+
+        cpu.faults.stack_overflow = FaultPolicy::Halt;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.step(), Err(Fault::StackOverflow { addr: 0x204 }));
+        assert_eq!(cpu.pc, 0x204, "PC should stay on the CALL");
+        assert_eq!(cpu.stack, vec![0x202, 0x204]);
+
+        for policy in [FaultPolicy::Ignore, FaultPolicy::Emulate] {
+            cpu.pc = 0x204;
+            cpu.faults.stack_overflow = policy;
+            cpu.step().unwrap();
+            assert_eq!(cpu.stack[2..], [0x206], "Stack grows past depth");
+            cpu.stack.truncate(2);
+        }
+    }
+
+    #[test]
+    pub fn test_pc_faults() {
+        let mut cpu = CPU::new();
+        cpu.faults = FaultPolicies::strict();
+
+        cpu.pc = 0x000;
+        assert_eq!(cpu.step(), Err(Fault::PcOutOfBounds { addr: 0x000 }));
+
+        cpu.pc = 0x0fff;
+        assert_eq!(cpu.step(), Err(Fault::PcOutOfBounds { addr: 0x0fff }));
+
+        cpu.pc = 0x0301;
+        assert_eq!(cpu.step(), Err(Fault::MisalignedPc { addr: 0x0301 }));
+
+        cpu.faults = FaultPolicies::new();
+        cpu.pc = 0x1300;
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0302, "PC should wrap around memory");
+    }
+
+    #[test]
+    pub fn test_SCU_n() {
+        let mut cpu = CPU::new();
+
+        cpu.width = 4;
+        cpu.height = 4;
+        cpu.vram = vec![0; 16];
+        cpu.vram[12..].fill(1);
+
+        cpu.execute(Instruction::SCU_n(2));
+
+        let (chunks, _) = cpu.vram.as_chunks::<4>();
+        assert_eq!(
+            chunks,
+            [[0, 0, 0, 0], [1, 1, 1, 1], [0, 0, 0, 0], [0, 0, 0, 0],]
+        );
+    }
+
+    #[test]
+    pub fn test_DRW_Vx_Vy_n_planes() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x300;
+        cpu.memory.memory[0x300..0x302].copy_from_slice(&[0b11000000, 0b10000000]);
+
+        cpu.execute(Instruction::PLANE_n(3));
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 0, 1));
+        assert_eq!(cpu.vram[0..3], [3, 1, 0], "Each plane reads its own row");
+        assert_eq!(cpu.vx[0xf], 0);
+
+        cpu.execute(Instruction::PLANE_n(2));
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 0, 1));
+        assert_eq!(cpu.vram[0..3], [1, 3, 0], "Only plane 2 should be drawn");
+        assert_eq!(cpu.vx[0xf], 1);
+
+        cpu.execute(Instruction::PLANE_n(0));
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 0, 1));
+        assert_eq!(cpu.vram[0..3], [1, 3, 0], "No planes should be drawn");
+    }
+
+    #[test]
+    pub fn test_CLS_planes() {
+        let mut cpu = CPU::new();
+        cpu.vram.fill(3);
+
+        cpu.execute(Instruction::PLANE_n(2));
+        cpu.execute(Instruction::CLS);
+        assert!(cpu.vram.iter().all(|p| *p == 1), "Only plane 2 is cleared");
+    }
+
+    #[test]
+    pub fn test_dirty_tracking() {
+        let mut cpu = CPU::new();
+        assert_eq!(cpu.dirty_rows(), Some(0..32), "A new display needs drawing");
+        cpu.clear_dirty();
+
+        // The 0 glyph is five rows tall
+        cpu.vx[1] = 10;
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 1, 5));
+        assert!(cpu.is_dirty() && cpu.frame_presented());
+        assert_eq!(cpu.dirty_rows(), Some(10..15));
+
+        cpu.vx[1] = 2;
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 1, 1));
+        assert_eq!(cpu.dirty_rows(), Some(2..15));
+
+        cpu.clear_dirty();
+        assert!(!cpu.is_dirty() && !cpu.frame_presented());
+
+        // Nothing to draw from an empty sprite, but the ROM still presented a frame
+        cpu.i = 0x300;
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 1, 5));
+        assert!(!cpu.is_dirty() && cpu.frame_presented());
+
+        cpu.execute(Instruction::CLS);
+        assert_eq!(cpu.dirty_rows(), Some(0..32));
+
+        cpu.clear_dirty();
+        cpu.execute(Instruction::CLS);
+        assert!(!cpu.is_dirty(), "Clearing a clear screen changes nothing");
+
+        cpu.execute(Instruction::SCR);
+        assert!(cpu.is_dirty());
+    }
+
+    #[test]
+    pub fn test_SCR_lores_half_scroll_quirk() {
+        let mut cpu = CPU::new();
+        cpu.vram[0] = 1;
+
+        cpu.quirks.lores_half_scroll = true;
+        cpu.execute(Instruction::SCR);
+        assert_eq!(cpu.vram[2], 1);
+
+        cpu.execute(Instruction::SCD_n(4));
+        assert_eq!(cpu.vram[2 * 64 + 2], 1);
+
+        // Hires always scrolls the full distance
+        cpu.execute(Instruction::HIRES);
+        cpu.vram.fill(0);
+        cpu.vram[0] = 1;
+        cpu.execute(Instruction::SCR);
+        assert_eq!(cpu.vram[4], 1);
+    }
+
+    #[test]
+    pub fn test_DRW_lores_8x16_sprites_quirk() {
+        let mut cpu = CPU::new();
+        for row in 0..32 {
+            cpu.memory.write(0x300 + row, 0xff);
+        }
+        cpu.i = 0x300;
+
+        cpu.quirks.lores_8x16_sprites = true;
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 0, 0));
+        assert_eq!(cpu.vram[15 * 64..15 * 64 + 16].iter().sum::<u8>(), 8);
+        assert_eq!(cpu.vram[16 * 64], 0);
+
+        cpu.execute(Instruction::CLS);
+        cpu.quirks.lores_8x16_sprites = false;
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 0, 0));
+        assert_eq!(cpu.vram[15 * 64..15 * 64 + 16].iter().sum::<u8>(), 16);
+    }
+
+    #[test]
+    pub fn test_DRW_hires_draw_flag_quirk() {
+        let mut cpu = CPU::new();
+        cpu.execute(Instruction::HIRES);
+        cpu.i = 0; // The 0 glyph
+
+        cpu.quirks.hires_draw_flag = true;
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 0, 5));
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 0, 5));
+        assert_eq!(cpu.vx[0xf], 5);
+
+        // Rows clipped at the bottom count too
+        cpu.vx[1] = 62;
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 1, 5));
+        assert_eq!(cpu.vx[0xf], 3);
+
+        cpu.quirks.hires_draw_flag = false;
+        cpu.vx[1] = 0;
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 1, 5));
+        cpu.execute(Instruction::DRW_Vx_Vy_n(0, 1, 5));
+        assert_eq!(cpu.vx[0xf], 1);
+    }
+
+    #[test]
+    pub fn test_LD_iI_Vx_memory_increment_by_x_quirk() {
+        let mut cpu = CPU::new();
+        cpu.quirks.memory = true;
+        cpu.i = 0x300;
+
+        cpu.execute(Instruction::LD_iI_Vx(3));
+        assert_eq!(cpu.i, 0x304);
+
+        cpu.quirks.memory_increment_by_x = true;
+        cpu.execute(Instruction::LD_iI_Vx(3));
+        assert_eq!(cpu.i, 0x307);
+        cpu.execute(Instruction::LD_Vx_iI(3));
+        assert_eq!(cpu.i, 0x30a);
+    }
+
+    #[test]
+    pub fn test_HIRES_resolution_clears_quirk() {
+        let mut cpu = CPU::new();
+        cpu.vram[0] = 1;
+        cpu.execute(Instruction::HIRES);
+        assert_eq!(cpu.vram[0], 1);
+
+        cpu.quirks.resolution_clears = true;
+        cpu.execute(Instruction::LORES);
+        assert!(cpu.vram.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    pub fn test_SCR_planes() {
+        let mut cpu = CPU::new();
+        cpu.vram[0] = 3;
+
+        cpu.execute(Instruction::PLANE_n(1));
+        cpu.execute(Instruction::SCR);
+        assert_eq!(cpu.vram[0..5], [2, 0, 0, 0, 1], "Only plane 1 scrolls");
+    }
+
+    #[test]
+    pub fn test_LD_iI_Vx_Vy() {
+        let mut cpu = CPU::new();
+        cpu.vx = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        cpu.i = 0x300;
+
+        cpu.execute(Instruction::LD_iI_Vx_Vy(2, 4));
+        assert_eq!(cpu.memory.memory[0x300..0x304], [2, 3, 4, 0]);
+        assert_eq!(cpu.i, 0x300, "I should not be modified");
+
+        cpu.execute(Instruction::LD_iI_Vx_Vy(6, 5));
+        assert_eq!(cpu.memory.memory[0x300..0x302], [6, 5]);
+    }
+
+    #[test]
+    pub fn test_LD_Vx_Vy_iI() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x300;
+        cpu.memory.memory[0x300..0x303].copy_from_slice(&[7, 8, 9]);
+
+        cpu.execute(Instruction::LD_Vx_Vy_iI(3, 1));
+        assert_eq!(cpu.vx[1..4], [9, 8, 7]);
+        assert_eq!(cpu.i, 0x300, "I should not be modified");
+    }
+
+    #[test]
+    pub fn test_LD_I_long() {
+        let mut cpu = CPU::new();
+        cpu.memory.load_program(&[0xF0, 0x00, 0x12, 0x34]).unwrap();
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.i, 0x1234);
+        assert_eq!(cpu.pc, 0x204, "PC should advance past both words");
+    }
+
+    #[test]
+    pub fn test_skip_over_LD_I_long() {
+        let mut cpu = CPU::new();
+        cpu.memory
+            .load_program(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x30, 0x01])
+            .unwrap();
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x206, "Skips should jump over all 4 bytes");
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x208, "Skips should only jump over 2 bytes");
+    }
+
+    #[test]
+    pub fn test_AUDIO_and_PITCH_Vx() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x300;
+        cpu.memory.memory[0x300..0x310].fill(0xaa);
+        cpu.vx[3] = 112;
+
+        cpu.execute(Instruction::AUDIO);
+        cpu.execute(Instruction::PITCH_Vx(3));
+
+        assert_eq!(cpu.pattern, [0xaa; 16]);
+        assert_eq!(cpu.pitch, 112);
+    }
 }
@@ -0,0 +1,276 @@
+use core::fmt;
+use std::ops::Range;
+
+use super::{instruction::Instruction, memory::Memory};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Cowgod,
+    Octo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Option<Instruction>,
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self
+            .bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        write!(f, "{:04X}  {:<13}{}", self.addr, bytes, self.text)
+    }
+}
+
+pub fn disassemble(memory: &Memory, range: Range<usize>, syntax: Syntax) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut addr = range.start;
+
+    while addr < range.end {
+        let remaining = range.end - addr;
+        let read = |offset: usize| memory.read((addr + offset) as u16);
+
+        let op = ((read(0) as u16) << 8) | read(1) as u16;
+        let next = ((read(2) as u16) << 8) | read(3) as u16;
+        let inst = Instruction::parse(op, next);
+        let size = inst.size() as usize;
+
+        let line = match inst {
+            Instruction::Unknown(_) => None,
+            Instruction::SYS_addr(_) if syntax == Syntax::Octo => None,
+            _ if size > remaining => None,
+            _ => Some(Line {
+                addr: addr as u16,
+                bytes: (0..size).map(read).collect(),
+                instruction: Some(inst),
+                text: mnemonic(inst, syntax),
+            }),
+        };
+
+        // Anything that doesn't decode is printed as data
+        let line = line.unwrap_or_else(|| {
+            let bytes: Vec<u8> = (0..remaining.min(2)).map(read).collect();
+            Line {
+                addr: addr as u16,
+                text: data(&bytes, syntax),
+                bytes,
+                instruction: None,
+            }
+        });
+
+        addr += line.bytes.len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+pub fn data(bytes: &[u8], syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Cowgod => format!(
+            "DB {}",
+            bytes
+                .iter()
+                .map(|b| format!("#{:02X}", b))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        Syntax::Octo => bytes
+            .iter()
+            .map(|b| format!("0x{:02X}", b))
+            .collect::<Vec<String>>()
+            .join(" "),
+    }
+}
+
+pub fn mnemonic(inst: Instruction, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Cowgod => cowgod(inst),
+        Syntax::Octo => octo(inst),
+    }
+}
+
+fn cowgod(inst: Instruction) -> String {
+    match inst {
+        Instruction::SYS_addr(addr) => format!("SYS #{:03X}", addr),
+        Instruction::CLS => "CLS".to_string(),
+        Instruction::RET => "RET".to_string(),
+        Instruction::JP_addr(addr) => format!("JP #{:03X}", addr),
+        Instruction::CALL_addr(addr) => format!("CALL #{:03X}", addr),
+        Instruction::SE_Vx_kk(x, kk) => format!("SE V{:X}, #{:02X}", x, kk),
+        Instruction::SNE_Vx_kk(x, kk) => format!("SNE V{:X}, #{:02X}", x, kk),
+        Instruction::SE_Vx_Vy(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::LD_Vx_kk(x, kk) => format!("LD V{:X}, #{:02X}", x, kk),
+        Instruction::ADD_Vx_kk(x, kk) => format!("ADD V{:X}, #{:02X}", x, kk),
+        Instruction::LD_Vx_Vy(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::OR_Vx_Vy(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::AND_Vx_Vy(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::XOR_Vx_Vy(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::ADD_Vx_Vy(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::SUB_Vx_Vy(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::SHR_Vx_Vy(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::SUBN_Vx_Vy(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::SHL_Vx_Vy(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SNE_Vx_Vy(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::LD_I_addr(addr) => format!("LD I, #{:03X}", addr),
+        Instruction::JP_Vx_addr(x, addr) => format!("JP V{:X}, #{:03X}", x, addr),
+        Instruction::RND_Vx_kk(x, kk) => format!("RND V{:X}, #{:02X}", x, kk),
+        Instruction::SKP_Vx(x) => format!("SKP V{:X}", x),
+        Instruction::SKNP_Vx(x) => format!("SKNP V{:X}", x),
+        Instruction::LD_Vx_DT(x) => format!("LD V{:X}, DT", x),
+        Instruction::LD_Vx_K(x) => format!("LD V{:X}, K", x),
+        Instruction::LD_DT_Vx(x) => format!("LD DT, V{:X}", x),
+        Instruction::LD_ST_Vx(x) => format!("LD ST, V{:X}", x),
+        Instruction::ADD_I_Vx(x) => format!("ADD I, V{:X}", x),
+        Instruction::LD_F_Vx(x) => format!("LD F, V{:X}", x),
+        Instruction::LD_B_Vx(x) => format!("LD B, V{:X}", x),
+        Instruction::LD_iI_Vx(x) => format!("LD [I], V{:X}", x),
+        Instruction::LD_Vx_iI(x) => format!("LD V{:X}, [I]", x),
+        Instruction::DRW_Vx_Vy_n(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction::SCD_n(n) => format!("SCD {}", n),
+        Instruction::SCR => "SCR".to_string(),
+        Instruction::SCL => "SCL".to_string(),
+        Instruction::EXIT => "EXIT".to_string(),
+        Instruction::LORES => "LOW".to_string(),
+        Instruction::HIRES => "HIGH".to_string(),
+        Instruction::LD_HF_Vx(x) => format!("LD HF, V{:X}", x),
+        Instruction::SAVE_Vx(x) => format!("LD R, V{:X}", x),
+        Instruction::LOAD_Vx(x) => format!("LD V{:X}, R", x),
+
+        Instruction::SCU_n(n) => format!("SCU {}", n),
+        Instruction::LD_iI_Vx_Vy(x, y) => format!("SAVE V{:X}, V{:X}", x, y),
+        Instruction::LD_Vx_Vy_iI(x, y) => format!("LOAD V{:X}, V{:X}", x, y),
+        Instruction::LD_I_long(addr) => format!("LD I, LONG #{:04X}", addr),
+        Instruction::PLANE_n(n) => format!("PLANE {}", n),
+        Instruction::AUDIO => "AUDIO".to_string(),
+        Instruction::PITCH_Vx(x) => format!("PITCH V{:X}", x),
+
+        Instruction::Unknown(op) => data(&op.to_be_bytes(), Syntax::Cowgod),
+    }
+}
+
+// Octo writes skips as the condition for running the next instruction, so they read inverted
+fn octo(inst: Instruction) -> String {
+    match inst {
+        Instruction::CLS => "clear".to_string(),
+        Instruction::RET => "return".to_string(),
+        Instruction::JP_addr(addr) => format!("jump 0x{:03X}", addr),
+        Instruction::CALL_addr(addr) => format!(":call 0x{:03X}", addr),
+        Instruction::SE_Vx_kk(x, kk) => format!("if v{:x} != 0x{:02X} then", x, kk),
+        Instruction::SNE_Vx_kk(x, kk) => format!("if v{:x} == 0x{:02X} then", x, kk),
+        Instruction::SE_Vx_Vy(x, y) => format!("if v{:x} != v{:x} then", x, y),
+        Instruction::LD_Vx_kk(x, kk) => format!("v{:x} := 0x{:02X}", x, kk),
+        Instruction::ADD_Vx_kk(x, kk) => format!("v{:x} += 0x{:02X}", x, kk),
+        Instruction::LD_Vx_Vy(x, y) => format!("v{:x} := v{:x}", x, y),
+        Instruction::OR_Vx_Vy(x, y) => format!("v{:x} |= v{:x}", x, y),
+        Instruction::AND_Vx_Vy(x, y) => format!("v{:x} &= v{:x}", x, y),
+        Instruction::XOR_Vx_Vy(x, y) => format!("v{:x} ^= v{:x}", x, y),
+        Instruction::ADD_Vx_Vy(x, y) => format!("v{:x} += v{:x}", x, y),
+        Instruction::SUB_Vx_Vy(x, y) => format!("v{:x} -= v{:x}", x, y),
+        Instruction::SHR_Vx_Vy(x, y) => format!("v{:x} >>= v{:x}", x, y),
+        Instruction::SUBN_Vx_Vy(x, y) => format!("v{:x} =- v{:x}", x, y),
+        Instruction::SHL_Vx_Vy(x, y) => format!("v{:x} <<= v{:x}", x, y),
+        Instruction::SNE_Vx_Vy(x, y) => format!("if v{:x} == v{:x} then", x, y),
+        Instruction::LD_I_addr(addr) => format!("i := 0x{:03X}", addr),
+        Instruction::JP_Vx_addr(_, addr) => format!("jump0 0x{:03X}", addr),
+        Instruction::RND_Vx_kk(x, kk) => format!("v{:x} := random 0x{:02X}", x, kk),
+        Instruction::SKP_Vx(x) => format!("if v{:x} -key then", x),
+        Instruction::SKNP_Vx(x) => format!("if v{:x} key then", x),
+        Instruction::LD_Vx_DT(x) => format!("v{:x} := delay", x),
+        Instruction::LD_Vx_K(x) => format!("v{:x} := key", x),
+        Instruction::LD_DT_Vx(x) => format!("delay := v{:x}", x),
+        Instruction::LD_ST_Vx(x) => format!("buzzer := v{:x}", x),
+        Instruction::ADD_I_Vx(x) => format!("i += v{:x}", x),
+        Instruction::LD_F_Vx(x) => format!("i := hex v{:x}", x),
+        Instruction::LD_B_Vx(x) => format!("bcd v{:x}", x),
+        Instruction::LD_iI_Vx(x) => format!("save v{:x}", x),
+        Instruction::LD_Vx_iI(x) => format!("load v{:x}", x),
+        Instruction::DRW_Vx_Vy_n(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        Instruction::SCD_n(n) => format!("scroll-down {}", n),
+        Instruction::SCR => "scroll-right".to_string(),
+        Instruction::SCL => "scroll-left".to_string(),
+        Instruction::EXIT => "exit".to_string(),
+        Instruction::LORES => "lores".to_string(),
+        Instruction::HIRES => "hires".to_string(),
+        Instruction::LD_HF_Vx(x) => format!("i := bighex v{:x}", x),
+        Instruction::SAVE_Vx(x) => format!("saveflags v{:x}", x),
+        Instruction::LOAD_Vx(x) => format!("loadflags v{:x}", x),
+
+        Instruction::SCU_n(n) => format!("scroll-up {}", n),
+        Instruction::LD_iI_Vx_Vy(x, y) => format!("save v{:x} - v{:x}", x, y),
+        Instruction::LD_Vx_Vy_iI(x, y) => format!("load v{:x} - v{:x}", x, y),
+        Instruction::LD_I_long(addr) => format!("i := long 0x{:04X}", addr),
+        Instruction::PLANE_n(n) => format!("plane {}", n),
+        Instruction::AUDIO => "audio".to_string(),
+        Instruction::PITCH_Vx(x) => format!("pitch := v{:x}", x),
+
+        // Octo has no mnemonic for machine code calls
+        Instruction::SYS_addr(_) | Instruction::Unknown(_) => data(&inst.encode(), Syntax::Octo),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, mnemonic, Syntax};
+    use crate::{instruction::Instruction, memory::Memory};
+
+    #[test]
+    fn disassembles_cowgod() {
+        let inst = Instruction::DRW_Vx_Vy_n(1, 2, 5);
+        assert_eq!(mnemonic(inst, Syntax::Cowgod), "DRW V1, V2, 5");
+        assert_eq!(
+            mnemonic(Instruction::LD_I_addr(0x2a0), Syntax::Cowgod),
+            "LD I, #2A0"
+        );
+    }
+
+    #[test]
+    fn disassembles_octo() {
+        let inst = Instruction::DRW_Vx_Vy_n(1, 2, 5);
+        assert_eq!(mnemonic(inst, Syntax::Octo), "sprite v1 v2 5");
+        assert_eq!(
+            mnemonic(Instruction::SE_Vx_kk(0xa, 3), Syntax::Octo),
+            "if va != 0x03 then"
+        );
+    }
+
+    #[test]
+    fn disassembles_memory_ranges() {
+        let mut m = Memory::new();
+        m.load_program(&[0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34, 0xFF, 0xFF, 0xD1])
+            .unwrap();
+
+        let listing = disassemble(&m, 0x200..0x209, Syntax::Cowgod)
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            listing,
+            [
+                "0200  00 E0        CLS",
+                "0202  F0 00 12 34  LD I, LONG #1234",
+                "0206  FF FF        DB #FF, #FF",
+                "0208  D1           DB #D1",
+            ]
+        );
+    }
+
+    #[test]
+    fn disassembles_truncated_long_loads_as_data() {
+        let mut m = Memory::new();
+        m.load_program(&[0xF0, 0x00, 0x12, 0x34]).unwrap();
+
+        let listing = disassemble(&m, 0x200..0x202, Syntax::Octo);
+        assert_eq!(listing.len(), 1);
+        assert_eq!(listing[0].text, "0xF0 0x00");
+    }
+}
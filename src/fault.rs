@@ -0,0 +1,77 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    InvalidOpcode { op: u16, addr: u16 },
+    StackUnderflow { addr: u16 },
+    StackOverflow { addr: u16 },
+    PcOutOfBounds { addr: u16 },
+    MisalignedPc { addr: u16 },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::InvalidOpcode { op, addr } => {
+                write!(f, "Invalid opcode ${:04x} at ${:04x}", op, addr)
+            }
+            Fault::StackUnderflow { addr } => write!(f, "Stack underflow at ${:04x}", addr),
+            Fault::StackOverflow { addr } => write!(f, "Stack overflow at ${:04x}", addr),
+            Fault::PcOutOfBounds { addr } => {
+                write!(f, "Executing outside of program memory at ${:04x}", addr)
+            }
+            Fault::MisalignedPc { addr } => write!(f, "Misaligned PC at ${:04x}", addr),
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FaultPolicy {
+    // Carry on without the offending operation where possible
+    Ignore,
+    // Stop and report the fault to the caller
+    Halt,
+    // Do what the original hardware would have done
+    Emulate,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FaultPolicies {
+    // Ignore: RET does nothing, Emulate: RET jumps to $0000
+    pub stack_underflow: FaultPolicy,
+    // The stack grows past its depth unless halting. Emulate can't do better, the VIP's
+    // stack runs on down into program memory and we keep the stack out of memory
+    pub stack_overflow: FaultPolicy,
+    // Ignore and Emulate both fetch from wherever PC points, wrapping at the memory size
+    pub pc_out_of_bounds: FaultPolicy,
+    pub misaligned_pc: FaultPolicy,
+}
+
+impl FaultPolicies {
+    pub fn new() -> FaultPolicies {
+        FaultPolicies {
+            stack_underflow: FaultPolicy::Emulate,
+            stack_overflow: FaultPolicy::Emulate,
+            pc_out_of_bounds: FaultPolicy::Emulate,
+            misaligned_pc: FaultPolicy::Emulate,
+        }
+    }
+
+    pub fn strict() -> FaultPolicies {
+        FaultPolicies {
+            stack_underflow: FaultPolicy::Halt,
+            stack_overflow: FaultPolicy::Halt,
+            pc_out_of_bounds: FaultPolicy::Halt,
+            misaligned_pc: FaultPolicy::Halt,
+        }
+    }
+}
+
+impl Default for FaultPolicies {
+    fn default() -> Self {
+        FaultPolicies::new()
+    }
+}
@@ -1,5 +1,21 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+use super::disassembler::{mnemonic, Syntax};
+
+// Each extension is a superset of the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum OpcodeSet {
+    Chip8,
+    // EXIT, LORES, HIRES, the big font and the flag registers
+    SuperChip10,
+    // Scrolling
+    SuperChip11,
+    XoChip,
+}
+
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     SYS_addr(u16),
     CLS,
@@ -45,10 +61,29 @@ pub enum Instruction {
     LD_HF_Vx(u8),
     SAVE_Vx(u8),
     LOAD_Vx(u8),
+
+    // XO-CHIP
+    SCU_n(u8),
+    LD_iI_Vx_Vy(u8, u8),
+    LD_Vx_Vy_iI(u8, u8),
+    LD_I_long(u16),
+    PLANE_n(u8),
+    AUDIO,
+    PITCH_Vx(u8),
+
+    Unknown(u16),
+}
+
+// Instructions print in Cowgod's syntax
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", mnemonic(*self, Syntax::Cowgod))
+    }
 }
 
 impl Instruction {
-    pub fn parse(op: u16) -> Instruction {
+    // `next` is the word following `op`, only consumed by the 4 byte `F000 NNNN`
+    pub fn parse(op: u16, next: u16) -> Instruction {
         let nibbles = (
             ((op & 0xf000) >> 12) as u8,
             ((op & 0x0f00) >> 8) as u8,
@@ -68,6 +103,7 @@ impl Instruction {
             (0x0, 0x0, 0xF, 0xE) => Instruction::LORES,
             (0x0, 0x0, 0xF, 0xF) => Instruction::HIRES,
             (0x0, 0x0, 0xC, n) => Instruction::SCD_n(n),
+            (0x0, 0x0, 0xD, n) => Instruction::SCU_n(n),
 
             // Special case for hires $0230
             (0x0, 0x2, 0x3, 0x0) => Instruction::CLS,
@@ -81,6 +117,8 @@ impl Instruction {
             (0x4, x, _, _) => Instruction::SNE_Vx_kk(x, kk),
 
             (0x5, x, y, 0x0) => Instruction::SE_Vx_Vy(x, y),
+            (0x5, x, y, 0x2) => Instruction::LD_iI_Vx_Vy(x, y),
+            (0x5, x, y, 0x3) => Instruction::LD_Vx_Vy_iI(x, y),
             (0x6, x, _, _) => Instruction::LD_Vx_kk(x, kk),
             (0x7, x, _, _) => Instruction::ADD_Vx_kk(x, kk),
 
@@ -105,6 +143,10 @@ impl Instruction {
             (0xE, x, 0x9, 0xE) => Instruction::SKP_Vx(x),
             (0xE, x, 0xA, 0x1) => Instruction::SKNP_Vx(x),
 
+            (0xF, 0x0, 0x0, 0x0) => Instruction::LD_I_long(next),
+            (0xF, n, 0x0, 0x1) => Instruction::PLANE_n(n),
+            (0xF, 0x0, 0x0, 0x2) => Instruction::AUDIO,
+
             (0xF, x, 0x0, 0x7) => Instruction::LD_Vx_DT(x),
             (0xF, x, 0x0, 0xA) => Instruction::LD_Vx_K(x),
             (0xF, x, 0x1, 0x5) => Instruction::LD_DT_Vx(x),
@@ -121,10 +163,292 @@ impl Instruction {
             (0xF, x, 0x7, 0x5) => Instruction::SAVE_Vx(x),
             (0xF, x, 0x8, 0x5) => Instruction::LOAD_Vx(x),
 
-            _ => panic!("Invalid opcode ${:04x}", op),
+            (0xF, x, 0x3, 0xA) => Instruction::PITCH_Vx(x),
+
+            _ => Instruction::Unknown(op),
+        }
+    }
+
+    // Constructed instructions can hold operands too wide for their field in the opcode
+    pub fn is_encodable(&self) -> bool {
+        let (nibbles, addr) = match *self {
+            Instruction::SYS_addr(addr)
+            | Instruction::JP_addr(addr)
+            | Instruction::CALL_addr(addr)
+            | Instruction::LD_I_addr(addr) => ([0; 3], addr),
+            // X is the top nibble of the address
+            Instruction::JP_Vx_addr(x, addr) if addr >> 8 != x as u16 => return false,
+            Instruction::JP_Vx_addr(_, addr) => ([0; 3], addr),
+
+            Instruction::SE_Vx_kk(x, _)
+            | Instruction::SNE_Vx_kk(x, _)
+            | Instruction::LD_Vx_kk(x, _)
+            | Instruction::ADD_Vx_kk(x, _)
+            | Instruction::RND_Vx_kk(x, _)
+            | Instruction::SKP_Vx(x)
+            | Instruction::SKNP_Vx(x)
+            | Instruction::LD_Vx_DT(x)
+            | Instruction::LD_Vx_K(x)
+            | Instruction::LD_DT_Vx(x)
+            | Instruction::LD_ST_Vx(x)
+            | Instruction::ADD_I_Vx(x)
+            | Instruction::LD_F_Vx(x)
+            | Instruction::LD_B_Vx(x)
+            | Instruction::LD_iI_Vx(x)
+            | Instruction::LD_Vx_iI(x)
+            | Instruction::SCD_n(x)
+            | Instruction::LD_HF_Vx(x)
+            | Instruction::SAVE_Vx(x)
+            | Instruction::LOAD_Vx(x)
+            | Instruction::SCU_n(x)
+            | Instruction::PLANE_n(x)
+            | Instruction::PITCH_Vx(x) => ([x, 0, 0], 0),
+
+            Instruction::SE_Vx_Vy(x, y)
+            | Instruction::LD_Vx_Vy(x, y)
+            | Instruction::OR_Vx_Vy(x, y)
+            | Instruction::AND_Vx_Vy(x, y)
+            | Instruction::XOR_Vx_Vy(x, y)
+            | Instruction::ADD_Vx_Vy(x, y)
+            | Instruction::SUB_Vx_Vy(x, y)
+            | Instruction::SHR_Vx_Vy(x, y)
+            | Instruction::SUBN_Vx_Vy(x, y)
+            | Instruction::SHL_Vx_Vy(x, y)
+            | Instruction::SNE_Vx_Vy(x, y)
+            | Instruction::LD_iI_Vx_Vy(x, y)
+            | Instruction::LD_Vx_Vy_iI(x, y) => ([x, y, 0], 0),
+            Instruction::DRW_Vx_Vy_n(x, y, n) => ([x, y, n], 0),
+
+            _ => ([0; 3], 0),
+        };
+
+        nibbles.iter().all(|&n| n <= 0xf) && addr <= 0xfff
+    }
+
+    // Opcode of the first word, encoding is canonical so aliases like $0230 become $00E0
+    pub fn opcode(&self) -> u16 {
+        debug_assert!(self.is_encodable(), "{:?} doesn't fit in an opcode", self);
+
+        let xy = |op: u16, x: u8, y: u8| op | ((x as u16 & 0xf) << 8) | ((y as u16 & 0xf) << 4);
+        let x = |op: u16, x: u8| xy(op, x, 0);
+        let xkk = |op: u16, x: u8, kk: u8| xy(op, x, 0) | kk as u16;
+
+        match *self {
+            Instruction::SYS_addr(addr) => addr & 0x0fff,
+            Instruction::CLS => 0x00E0,
+            Instruction::RET => 0x00EE,
+            Instruction::JP_addr(addr) => 0x1000 | (addr & 0x0fff),
+            Instruction::CALL_addr(addr) => 0x2000 | (addr & 0x0fff),
+            Instruction::SE_Vx_kk(vx, kk) => xkk(0x3000, vx, kk),
+            Instruction::SNE_Vx_kk(vx, kk) => xkk(0x4000, vx, kk),
+            Instruction::SE_Vx_Vy(vx, vy) => xy(0x5000, vx, vy),
+            Instruction::LD_Vx_kk(vx, kk) => xkk(0x6000, vx, kk),
+            Instruction::ADD_Vx_kk(vx, kk) => xkk(0x7000, vx, kk),
+            Instruction::LD_Vx_Vy(vx, vy) => xy(0x8000, vx, vy),
+            Instruction::OR_Vx_Vy(vx, vy) => xy(0x8001, vx, vy),
+            Instruction::AND_Vx_Vy(vx, vy) => xy(0x8002, vx, vy),
+            Instruction::XOR_Vx_Vy(vx, vy) => xy(0x8003, vx, vy),
+            Instruction::ADD_Vx_Vy(vx, vy) => xy(0x8004, vx, vy),
+            Instruction::SUB_Vx_Vy(vx, vy) => xy(0x8005, vx, vy),
+            Instruction::SHR_Vx_Vy(vx, vy) => xy(0x8006, vx, vy),
+            Instruction::SUBN_Vx_Vy(vx, vy) => xy(0x8007, vx, vy),
+            Instruction::SHL_Vx_Vy(vx, vy) => xy(0x800E, vx, vy),
+            Instruction::SNE_Vx_Vy(vx, vy) => xy(0x9000, vx, vy),
+            Instruction::LD_I_addr(addr) => 0xA000 | (addr & 0x0fff),
+            Instruction::JP_Vx_addr(vx, addr) => x(0xB000, vx) | (addr & 0x0fff),
+            Instruction::RND_Vx_kk(vx, kk) => xkk(0xC000, vx, kk),
+            Instruction::SKP_Vx(vx) => x(0xE09E, vx),
+            Instruction::SKNP_Vx(vx) => x(0xE0A1, vx),
+            Instruction::LD_Vx_DT(vx) => x(0xF007, vx),
+            Instruction::LD_Vx_K(vx) => x(0xF00A, vx),
+            Instruction::LD_DT_Vx(vx) => x(0xF015, vx),
+            Instruction::LD_ST_Vx(vx) => x(0xF018, vx),
+            Instruction::ADD_I_Vx(vx) => x(0xF01E, vx),
+            Instruction::LD_F_Vx(vx) => x(0xF029, vx),
+            Instruction::LD_B_Vx(vx) => x(0xF033, vx),
+            Instruction::LD_iI_Vx(vx) => x(0xF055, vx),
+            Instruction::LD_Vx_iI(vx) => x(0xF065, vx),
+            Instruction::DRW_Vx_Vy_n(vx, vy, n) => xy(0xD000, vx, vy) | (n as u16 & 0xf),
+            Instruction::SCD_n(n) => 0x00C0 | (n as u16 & 0xf),
+            Instruction::SCR => 0x00FB,
+            Instruction::SCL => 0x00FC,
+            Instruction::EXIT => 0x00FD,
+            Instruction::LORES => 0x00FE,
+            Instruction::HIRES => 0x00FF,
+            Instruction::LD_HF_Vx(vx) => x(0xF030, vx),
+            Instruction::SAVE_Vx(vx) => x(0xF075, vx),
+            Instruction::LOAD_Vx(vx) => x(0xF085, vx),
+
+            Instruction::SCU_n(n) => 0x00D0 | (n as u16 & 0xf),
+            Instruction::LD_iI_Vx_Vy(vx, vy) => xy(0x5002, vx, vy),
+            Instruction::LD_Vx_Vy_iI(vx, vy) => xy(0x5003, vx, vy),
+            Instruction::LD_I_long(_) => 0xF000,
+            Instruction::PLANE_n(n) => x(0xF001, n),
+            Instruction::AUDIO => 0xF002,
+            Instruction::PITCH_Vx(vx) => x(0xF03A, vx),
+
+            Instruction::Unknown(op) => op,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.opcode().to_be_bytes().to_vec();
+
+        if let Instruction::LD_I_long(addr) = self {
+            bytes.extend(addr.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    // Size of the instruction in bytes
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LD_I_long(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // The first extension to define the instruction
+    pub fn opcode_set(&self) -> OpcodeSet {
+        match self {
+            Instruction::EXIT
+            | Instruction::LORES
+            | Instruction::HIRES
+            | Instruction::LD_HF_Vx(_)
+            | Instruction::SAVE_Vx(_)
+            | Instruction::LOAD_Vx(_) => OpcodeSet::SuperChip10,
+
+            Instruction::SCD_n(_) | Instruction::SCR | Instruction::SCL => OpcodeSet::SuperChip11,
+
+            Instruction::SCU_n(_)
+            | Instruction::LD_iI_Vx_Vy(_, _)
+            | Instruction::LD_Vx_Vy_iI(_, _)
+            | Instruction::LD_I_long(_)
+            | Instruction::PLANE_n(_)
+            | Instruction::AUDIO
+            | Instruction::PITCH_Vx(_) => OpcodeSet::XoChip,
+
+            _ => OpcodeSet::Chip8,
+        }
+    }
+
+    // Size of the instruction starting with `op`, used to skip over it
+    pub fn size_of(op: u16) -> u16 {
+        if op == 0xF000 {
+            4
+        } else {
+            2
         }
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::Instruction;
+
+    #[test]
+    fn parses_xochip_instructions() {
+        assert!(matches!(
+            Instruction::parse(0xF000, 0x1234),
+            Instruction::LD_I_long(0x1234)
+        ));
+        assert!(matches!(
+            Instruction::parse(0x5122, 0),
+            Instruction::LD_iI_Vx_Vy(1, 2)
+        ));
+        assert!(matches!(
+            Instruction::parse(0x5343, 0),
+            Instruction::LD_Vx_Vy_iI(3, 4)
+        ));
+        assert!(matches!(
+            Instruction::parse(0xF301, 0),
+            Instruction::PLANE_n(3)
+        ));
+        assert!(matches!(Instruction::parse(0xF002, 0), Instruction::AUDIO));
+        assert!(matches!(
+            Instruction::parse(0xF53A, 0),
+            Instruction::PITCH_Vx(5)
+        ));
+        assert!(matches!(
+            Instruction::parse(0x00D4, 0),
+            Instruction::SCU_n(4)
+        ));
+    }
+
+    #[test]
+    fn invalid_opcodes_are_unknown() {
+        assert!(matches!(
+            Instruction::parse(0x5121, 0),
+            Instruction::Unknown(0x5121)
+        ));
+        assert!(matches!(
+            Instruction::parse(0xFFFF, 0),
+            Instruction::Unknown(0xFFFF)
+        ));
+    }
+
+    #[test]
+    fn encoding_round_trips_every_opcode() {
+        for op in 0..=0xFFFF {
+            let inst = Instruction::parse(op, 0xbeef);
+
+            assert_eq!(
+                Instruction::parse(inst.opcode(), 0xbeef),
+                inst,
+                "${:04x} should decode to the same instruction after encoding",
+                op
+            );
+
+            if op != 0x0230 {
+                assert_eq!(inst.opcode(), op, "${:04x} should encode to itself", op);
+            }
+        }
+    }
+
+    #[test]
+    fn encoding_aliases_are_canonical() {
+        assert_eq!(Instruction::parse(0x0230, 0).encode(), [0x00, 0xE0]);
+    }
+
+    #[test]
+    fn encoding_includes_the_long_address() {
+        let inst = Instruction::LD_I_long(0x1234);
+
+        assert_eq!(inst.encode(), [0xF0, 0x00, 0x12, 0x34]);
+        assert_eq!(Instruction::parse(0xF000, 0x1234), inst);
+        assert_eq!(inst.encode().len(), inst.size() as usize);
+    }
+
+    #[test]
+    fn encoding_constructed_instructions() {
+        assert_eq!(Instruction::JP_Vx_addr(2, 0x245).encode(), [0xB2, 0x45]);
+        assert_eq!(Instruction::DRW_Vx_Vy_n(1, 2, 15).encode(), [0xD1, 0x2F]);
+        assert_eq!(Instruction::PLANE_n(3).encode(), [0xF3, 0x01]);
+        assert_eq!(
+            Instruction::LD_I_long(0xffff).encode(),
+            [0xF0, 0x00, 0xff, 0xff]
+        );
+
+        assert!(!Instruction::JP_addr(0x1234).is_encodable());
+        assert!(!Instruction::JP_Vx_addr(2, 0x345).is_encodable());
+        assert!(!Instruction::LD_Vx_kk(16, 0).is_encodable());
+        assert!(!Instruction::DRW_Vx_Vy_n(0, 0, 16).is_encodable());
+        assert!(Instruction::Unknown(0xffff).is_encodable());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "doesn't fit in an opcode")]
+    fn encoding_rejects_wide_operands() {
+        Instruction::JP_addr(0x1234).encode();
+    }
+
+    #[test]
+    fn long_instructions_are_four_bytes() {
+        assert_eq!(Instruction::parse(0xF000, 0x1234).size(), 4);
+        assert_eq!(Instruction::parse(0xF002, 0x1234).size(), 2);
+        assert_eq!(Instruction::size_of(0xF000), 4);
+        assert_eq!(Instruction::size_of(0x1200), 2);
+    }
+}
@@ -1,11 +1,22 @@
-#![feature(exclusive_range_pattern)]
-#![feature(slice_pattern)]
-#![feature(slice_as_chunks)]
-
+pub mod analyzer;
+pub mod assembler;
+pub mod audio;
+pub mod config;
 pub mod cpu;
+pub mod disassembler;
+pub mod fault;
 pub mod instruction;
 pub mod memory;
+pub mod movie;
+pub mod octo;
+pub mod platform;
 pub mod quirks;
+pub mod render;
+pub mod rewind;
+pub mod romdb;
+pub mod savestate;
+pub mod screenshot;
+pub mod timing;
 
 pub mod chip8;
 
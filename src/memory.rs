@@ -1,4 +1,5 @@
-use serde::Serialize;
+use core::fmt;
+use serde::{Deserialize, Serialize};
 
 pub const FONT_5_5: [u8; 0x50] = //include_bytes!("data/rom.bin");
     [
@@ -40,10 +41,57 @@ pub const FONT_10_10: [u8; 0xA0] = [
     0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
 ];
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontSet {
+    // Only the small hexadecimal digits
+    Chip8,
+    // Adds big decimal digits
+    SuperChip,
+    // Adds big hexadecimal digits
+    Octo,
+}
+
+pub const MEMORY_2K: usize = 0x0800; // COSMAC VIP base model
+pub const MEMORY_4K: usize = 0x1000;
+pub const MEMORY_64K: usize = 0x10000; // XO-CHIP
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidMemorySize(pub usize);
+
+impl fmt::Display for InvalidMemorySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Memory size must be between $800 and $10000 bytes, not ${:x}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidMemorySize {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramTooLarge {
+    pub size: usize,
+    // Bytes from $200 to the end of memory
+    pub space: usize,
+}
+
+impl fmt::Display for ProgramTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Program is {} bytes, only {} fit in memory",
+            self.size, self.space
+        )
+    }
+}
+
+impl std::error::Error for ProgramTooLarge {}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Memory {
-    #[serde(serialize_with = "<[_]>::serialize")]
-    pub memory: [u8; 0x1000],
+    pub memory: Vec<u8>,
 }
 
 impl Default for Memory {
@@ -54,35 +102,71 @@ impl Default for Memory {
 
 impl Memory {
     pub fn new() -> Memory {
+        Memory::with_size(MEMORY_4K).unwrap()
+    }
+
+    pub fn with_size(size: usize) -> Result<Memory, InvalidMemorySize> {
+        if !(MEMORY_2K..=MEMORY_64K).contains(&size) {
+            return Err(InvalidMemorySize(size));
+        }
+
         let mut m = Memory {
-            memory: [0; 0x1000],
+            memory: vec![0; size],
         };
         m.reset();
-        m
+        Ok(m)
+    }
+
+    pub fn size(&self) -> usize {
+        self.memory.len()
     }
 
     pub fn reset(&mut self) {
         self.memory.fill(0);
+        self.load_font(FontSet::Octo);
+    }
+
+    pub fn load_font(&mut self, font: FontSet) {
+        let big = match font {
+            FontSet::Chip8 => 0,
+            FontSet::SuperChip => 10 * 10,
+            FontSet::Octo => FONT_10_10.len(),
+        };
+
         self.memory[0x000..0x050].copy_from_slice(&FONT_5_5);
-        self.memory[0x050..0x0F0].copy_from_slice(&FONT_10_10);
+        self.memory[0x050..0x050 + big].copy_from_slice(&FONT_10_10[..big]);
+        self.memory[0x050 + big..0x0F0].fill(0);
     }
 
-    pub fn load_program(&mut self, program: &[u8]) {
-        self.memory[0x200..(0x200 + program.len())].copy_from_slice(&program);
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), ProgramTooLarge> {
+        let space = self.size() - 0x200;
+        if program.len() > space {
+            return Err(ProgramTooLarge {
+                size: program.len(),
+                space,
+            });
+        }
+
+        self.memory[0x200..(0x200 + program.len())].copy_from_slice(program);
+        Ok(())
     }
 
     pub fn read(&self, addr: u16) -> u8 {
-        self.memory[(addr & 0xfff) as usize]
+        self.memory[addr as usize % self.memory.len()]
     }
 
     pub fn write(&mut self, addr: u16, byte: u8) {
-        self.memory[(addr & 0xfff) as usize] = byte;
+        let size = self.memory.len();
+        self.memory[addr as usize % size] = byte;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Memory, FONT_10_10, FONT_5_5};
+    use super::{
+        FontSet, InvalidMemorySize, Memory, ProgramTooLarge, FONT_10_10, FONT_5_5, MEMORY_2K,
+        MEMORY_64K,
+    };
 
     #[test]
     fn memory_contains_5x5_font_at_0x00() {
@@ -98,6 +182,19 @@ mod tests {
         assert_eq!(m.memory[0x050..0x0F0], FONT_10_10);
     }
 
+    #[test]
+    fn memory_loads_smaller_font_sets() {
+        let mut m = Memory::new();
+
+        m.load_font(FontSet::SuperChip);
+        assert_eq!(m.memory[0x050..0x0B4], FONT_10_10[..100]);
+        assert!(m.memory[0x0B4..0x0F0].iter().all(|&b| b == 0));
+
+        m.load_font(FontSet::Chip8);
+        assert_eq!(m.memory[0..0x050], FONT_5_5);
+        assert!(m.memory[0x050..0x0F0].iter().all(|&b| b == 0));
+    }
+
     #[test]
     fn memory_writes_work() {
         let mut m = Memory::new();
@@ -127,14 +224,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn memory_wraps_at_its_size() {
+        let mut m = Memory::with_size(MEMORY_2K).unwrap();
+
+        m.write(0x0a00, 0xee);
+        assert_eq!(m.memory[0x200], 0xee);
+        assert_eq!(m.read(0x1200), 0xee);
+    }
+
+    #[test]
+    fn memory_64k_does_not_wrap() {
+        let mut m = Memory::with_size(MEMORY_64K).unwrap();
+
+        m.write(0x1200, 0xcc);
+        assert_eq!(m.memory[0x1200], 0xcc);
+        assert_eq!(m.memory[0x0200], 0x00);
+        assert_eq!(m.read(0xffff), 0x00);
+    }
+
+    #[test]
+    fn memory_rejects_odd_sizes() {
+        assert_eq!(
+            Memory::with_size(0x400).unwrap_err(),
+            InvalidMemorySize(0x400)
+        );
+        assert!(Memory::with_size(MEMORY_64K + 1).is_err());
+    }
+
     #[test]
     fn memory_loads_programs() {
         let mut m = Memory::new();
-        m.load_program(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]);
+        m.load_program(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef])
+            .unwrap();
 
         assert_eq!(
             m.memory[0x200..0x209],
             [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x00]
         );
     }
+
+    #[test]
+    fn memory_rejects_programs_that_do_not_fit() {
+        let mut m = Memory::with_size(MEMORY_2K).unwrap();
+
+        assert!(m.load_program(&[0xff; 0x600]).is_ok());
+        assert_eq!(
+            m.load_program(&[0xff; 0x601]),
+            Err(ProgramTooLarge {
+                size: 0x601,
+                space: 0x600
+            })
+        );
+    }
 }
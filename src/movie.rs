@@ -0,0 +1,286 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use super::{cpu::CPU, instruction::OpcodeSet, memory::Memory, quirks::Quirks, timing::Timing};
+
+pub const VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEvent {
+    pub frame: u64,
+    // Cycles into the frame, input between frames happens at 0
+    pub cycle: u32,
+    pub key: u8,
+    pub down: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Movie {
+    pub version: u32,
+    pub rom_sha1: String,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub clock_speed: u32,
+    pub memory_size: usize,
+    pub stack_depth: usize,
+    pub opcodes: OpcodeSet,
+    // Bytes the ROM doesn't account for, like the platform's font and any pokes
+    pub memory: Vec<(u16, u8)>,
+    pub seed: u64,
+    pub events: Vec<KeyEvent>,
+    pub frames: u64,
+    // SHA-1 of vram and memory after the last frame
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    VersionMismatch { expected: u32, found: u32 },
+    RomMismatch { expected: String, found: String },
+    Desync { expected: String, found: String },
+    Decode(String),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::VersionMismatch { expected, found } => write!(
+                f,
+                "Movie version {} does not match version {}",
+                found, expected
+            ),
+            MovieError::RomMismatch { expected, found } => {
+                write!(f, "Movie was recorded with ROM {}, not {}", expected, found)
+            }
+            MovieError::Desync { expected, found } => write!(
+                f,
+                "Playback desynced, expected checksum {} but found {}",
+                expected, found
+            ),
+            MovieError::Decode(err) => write!(f, "Corrupt movie: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    Sha1::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn checksum(cpu: &CPU) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(&cpu.vram);
+    hasher.update(&cpu.memory.memory);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl Movie {
+    pub fn new(rom: &[u8], cpu: &CPU, seed: u64) -> Movie {
+        let mut fresh = Memory::with_size(cpu.memory.size()).unwrap_or_default();
+        // Recording started with the ROM loaded, so it fits
+        let _ = fresh.load_program(rom);
+        let memory = cpu
+            .memory
+            .memory
+            .iter()
+            .zip(&fresh.memory)
+            .enumerate()
+            .filter(|(_, (byte, fresh))| byte != fresh)
+            .map(|(addr, (&byte, _))| (addr as u16, byte))
+            .collect();
+
+        Movie {
+            version: VERSION,
+            rom_sha1: sha1_hex(rom),
+            quirks: cpu.quirks.clone(),
+            timing: cpu.timing,
+            clock_speed: cpu.clock_speed,
+            memory_size: cpu.memory.size(),
+            stack_depth: cpu.stack_depth,
+            opcodes: cpu.opcodes,
+            memory,
+            seed,
+            events: vec![],
+            frames: 0,
+            checksum: String::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Movies always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Movie, MovieError> {
+        let movie: Movie =
+            serde_json::from_str(json).map_err(|err| MovieError::Decode(err.to_string()))?;
+        if movie.version != VERSION {
+            return Err(MovieError::VersionMismatch {
+                expected: VERSION,
+                found: movie.version,
+            });
+        }
+        Ok(movie)
+    }
+}
+
+// Feeds a movie's events back in order
+pub struct Player {
+    pub movie: Movie,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Player {
+        Player { movie, next: 0 }
+    }
+
+    // Events due by `cycle` cycles into `frame`
+    pub fn due(&mut self, frame: u64, cycle: u32) -> &[KeyEvent] {
+        let start = self.next;
+        while let Some(event) = self.movie.events.get(self.next) {
+            if event.frame > frame || (event.frame == frame && event.cycle > cycle) {
+                break;
+            }
+            self.next += 1;
+        }
+        &self.movie.events[start..self.next]
+    }
+
+    pub fn finished(&self, frame: u64) -> bool {
+        frame >= self.movie.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{platform::Platform, Chip8};
+
+    const ROM: &[u8] = include_bytes!("test_data/6-keypad.ch8");
+
+    // Pick the FX0A test from the menu, then press and release a few keys
+    fn record() -> (Movie, Chip8) {
+        let mut c = Chip8::new();
+        c.load_program(ROM).unwrap();
+        c.start_recording(ROM, 42);
+
+        for frame in 0..120 {
+            match frame {
+                30 => c.keydown(0x3),
+                40 => c.keyup(0x3),
+                50 => c.keydown(0xa),
+                55 => c.keyup(0xa),
+                70 => c.keydown(0x5),
+                90 => c.keyup(0x5),
+                _ => {}
+            }
+            c.tick().unwrap();
+        }
+
+        (c.stop_recording().unwrap(), c)
+    }
+
+    fn play(movie: Movie) -> (Chip8, Result<(), MovieError>) {
+        let mut c = Chip8::new();
+        c.load_program(ROM).unwrap();
+        c.start_playback(movie, ROM).unwrap();
+
+        while !c.playback_finished() {
+            // Live input is ignored during playback
+            c.keydown(0x1);
+            c.tick().unwrap();
+        }
+
+        let result = c.verify_playback();
+        (c, result)
+    }
+
+    #[test]
+    fn playback_matches_the_recording() {
+        let (movie, recorded) = record();
+        assert_eq!(movie.events.len(), 6);
+        assert_eq!(movie.frames, 120);
+
+        let (played, result) = play(Movie::from_json(&movie.to_json()).unwrap());
+        assert_eq!(result, Ok(()));
+        assert_eq!(played.cpu.vram, recorded.cpu.vram);
+        assert_eq!(played.cpu.memory.memory, recorded.cpu.memory.memory);
+    }
+
+    #[test]
+    fn playback_sets_up_the_recorded_machine() {
+        let mut c = Chip8::new();
+        c.set_platform(&Platform::schip_1_0()).unwrap();
+        c.load_program(ROM).unwrap();
+        c.cpu.memory.write(0xe00, 0x42);
+        c.start_recording(ROM, 1);
+
+        // A key pressed while single stepping lands mid-frame
+        c.step().unwrap();
+        c.keydown(0x5);
+        for _ in 0..10 {
+            c.tick().unwrap();
+        }
+        let movie = c.stop_recording().unwrap();
+        assert_eq!(movie.events[0].cycle, 8);
+        assert!(movie.memory.contains(&(0xe00, 0x42)));
+
+        let mut played = Chip8::new();
+        played.load_program(ROM).unwrap();
+        played.start_playback(movie, ROM).unwrap();
+        assert_eq!(played.cpu.opcodes, OpcodeSet::SuperChip10);
+        assert_eq!(
+            played.cpu.memory.memory[0x050..0x0f0],
+            c.cpu.memory.memory[0x050..0x0f0]
+        );
+
+        while !played.playback_finished() {
+            played.tick().unwrap();
+        }
+        assert_eq!(played.verify_playback(), Ok(()));
+    }
+
+    #[test]
+    fn playback_detects_desyncs() {
+        // Without input the ROM stays on its menu
+        let (mut movie, _) = record();
+        movie.events.clear();
+
+        let (_, result) = play(movie);
+        assert!(matches!(result, Err(MovieError::Desync { .. })));
+    }
+
+    #[test]
+    fn playback_checks_the_rom() {
+        let (movie, _) = record();
+
+        let mut c = Chip8::new();
+        assert!(matches!(
+            c.start_playback(movie, b"other rom"),
+            Err(MovieError::RomMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let (mut movie, _) = record();
+        movie.version = 99;
+
+        assert_eq!(
+            Movie::from_json(&movie.to_json()),
+            Err(MovieError::VersionMismatch {
+                expected: VERSION,
+                found: 99
+            })
+        );
+    }
+}
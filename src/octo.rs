@@ -0,0 +1,1107 @@
+use std::collections::HashMap;
+
+use super::{assembler::AsmError, instruction::Instruction};
+
+pub const ORIGIN: u16 = 0x200;
+
+// Hard limit on macro expansions, so recursive macros fail instead of hanging
+const MAX_EXPANSIONS: usize = 10_000;
+
+pub struct Program {
+    pub image: Vec<u8>,
+    pub labels: HashMap<String, u16>,
+}
+
+impl Program {
+    // Label for an address, for showing symbols in the debugger
+    pub fn symbol(&self, addr: u16) -> Option<&str> {
+        self.labels
+            .iter()
+            .filter(|(_, a)| **a == addr)
+            .map(|(name, _)| name.as_str())
+            .min()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    // Low 12 bits of the instruction at the address
+    Addr,
+    // Word following an `F000` long load
+    Long,
+    // `:unpack`, high nibble then low byte of the label
+    UnpackHigh(u8),
+    UnpackLong,
+    UnpackLow,
+}
+
+enum Flow {
+    Loop { start: u16, breaks: Vec<u16> },
+    Branch { jump: u16 },
+}
+
+enum Value {
+    Known(i64),
+    Label(String),
+}
+
+enum Operand {
+    Reg(u8),
+    Value(i64),
+}
+
+enum Condition {
+    Skip(Instruction, Instruction),
+    // Relational conditions compute VF first, then skip on its value
+    Compare(Vec<Instruction>, u8),
+}
+
+fn error<T>(token: &Token, message: impl Into<String>) -> Result<T, AsmError> {
+    Err(AsmError {
+        line: token.line,
+        column: token.column,
+        message: message.into(),
+    })
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+
+    for (index, line) in source.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut pos = 0;
+
+        while pos < chars.len() {
+            if chars[pos] == '#' {
+                break;
+            }
+            if chars[pos].is_whitespace() {
+                pos += 1;
+                continue;
+            }
+
+            let start = pos;
+            if chars[pos] == '{' || chars[pos] == '}' {
+                pos += 1;
+            } else {
+                while pos < chars.len()
+                    && !chars[pos].is_whitespace()
+                    && chars[pos] != '{'
+                    && chars[pos] != '}'
+                {
+                    pos += 1;
+                }
+            }
+
+            tokens.push(Token {
+                text: chars[start..pos].iter().collect(),
+                line: index + 1,
+                column: start + 1,
+            });
+        }
+    }
+
+    tokens
+}
+
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<i64>().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    pos: usize,
+    expansions: usize,
+
+    image: Vec<u8>,
+    here: u16,
+
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+
+    fixups: Vec<(u16, Fixup, Token)>,
+    flow: Vec<(Flow, Token)>,
+}
+
+impl Compiler {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    // The previous token, so errors at the end of the input still have a position
+    fn expect(&mut self, what: &str) -> Result<Token, AsmError> {
+        match self.next() {
+            Some(token) => Ok(token),
+            None => {
+                let last = self.tokens.last().cloned().unwrap_or(Token {
+                    text: String::new(),
+                    line: 1,
+                    column: 1,
+                });
+                error(&last, format!("Expected {} at the end of the input", what))
+            }
+        }
+    }
+
+    fn expect_text(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.expect(text)?;
+        if token.text != text {
+            return error(
+                &token,
+                format!("Expected '{}', found '{}'", text, token.text),
+            );
+        }
+        Ok(token)
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        let offset = (addr - ORIGIN) as usize;
+        if self.image.len() <= offset {
+            self.image.resize(offset + 1, 0);
+        }
+        self.image[offset] = byte;
+    }
+
+    fn emit_bytes(&mut self, token: &Token, bytes: &[u8]) -> Result<(), AsmError> {
+        for byte in bytes {
+            // Only wrapping past $FFFF takes us below the origin
+            if self.here < ORIGIN {
+                return error(token, "Program does not fit in 64K of memory");
+            }
+            self.write(self.here, *byte);
+            self.here = self.here.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, token: &Token, inst: Instruction) -> Result<u16, AsmError> {
+        let addr = self.here;
+        self.emit_bytes(token, &inst.encode())?;
+        Ok(addr)
+    }
+
+    fn patch(&mut self, addr: u16, fixup: Fixup, target: u16) {
+        let offset = (addr - ORIGIN) as usize;
+        match fixup {
+            Fixup::Addr => {
+                let high = self.image[offset] & 0xf0;
+                self.write(addr, high | ((target >> 8) & 0xf) as u8);
+                self.write(addr + 1, target as u8);
+            }
+            Fixup::Long => {
+                self.write(addr + 2, (target >> 8) as u8);
+                self.write(addr + 3, target as u8);
+            }
+            Fixup::UnpackHigh(nibble) => {
+                self.write(addr + 1, (nibble << 4) | ((target >> 8) & 0xf) as u8);
+            }
+            Fixup::UnpackLong => self.write(addr + 1, (target >> 8) as u8),
+            Fixup::UnpackLow => self.write(addr + 1, target as u8),
+        }
+    }
+
+    fn register(&self, token: &Token) -> Option<u8> {
+        if let Some(x) = self.aliases.get(&token.text) {
+            return Some(*x);
+        }
+        let digits = token
+            .text
+            .strip_prefix('v')
+            .or(token.text.strip_prefix('V'))?;
+        if digits.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digits, 16).ok()
+    }
+
+    fn expect_register(&mut self) -> Result<u8, AsmError> {
+        let token = self.expect("a register")?;
+        match self.register(&token) {
+            Some(x) => Ok(x),
+            None => error(
+                &token,
+                format!("Expected a register, found '{}'", token.text),
+            ),
+        }
+    }
+
+    fn value(&mut self) -> Result<(Value, Token), AsmError> {
+        let token = self.expect("a value")?;
+
+        if token.text == "{" {
+            let value = self.calc()?;
+            return Ok((Value::Known(value as i64), token));
+        }
+        if let Some(n) = number(&token.text) {
+            return Ok((Value::Known(n), token));
+        }
+        if let Some(n) = self.constants.get(&token.text) {
+            return Ok((Value::Known(*n as i64), token));
+        }
+        if let Some(addr) = self.labels.get(&token.text) {
+            return Ok((Value::Known(*addr as i64), token));
+        }
+        if self.register(&token).is_some() || token.text.starts_with(':') {
+            return error(&token, format!("Expected a value, found '{}'", token.text));
+        }
+
+        Ok((Value::Label(token.text.clone()), token))
+    }
+
+    // A value that must be known now, such as an immediate byte
+    fn known(&mut self, min: i64, max: i64) -> Result<i64, AsmError> {
+        match self.value()? {
+            (Value::Known(n), token) if n < min || n > max => error(
+                &token,
+                format!("Value {} out of range {}..={}", n, min, max),
+            ),
+            (Value::Known(n), _) => Ok(n),
+            (Value::Label(name), token) => error(&token, format!("Undefined name {}", name)),
+        }
+    }
+
+    // Emit an instruction referencing an address, which may be a forward label
+    fn emit_addr(
+        &mut self,
+        token: &Token,
+        inst: fn(u16) -> Instruction,
+        fixup: Fixup,
+        max: i64,
+    ) -> Result<(), AsmError> {
+        let (value, target) = self.value()?;
+        match value {
+            Value::Known(n) if n < 0 || n > max => {
+                error(&target, format!("Address {} out of range 0..={}", n, max))
+            }
+            Value::Known(n) => self.emit(token, inst(n as u16)).map(|_| ()),
+            Value::Label(_) => {
+                let addr = self.emit(token, inst(0))?;
+                self.fixups.push((addr, fixup, target));
+                Ok(())
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        if let Some(x) = self.tokens.get(self.pos).and_then(|t| self.register(t)) {
+            self.pos += 1;
+            return Ok(Operand::Reg(x));
+        }
+        Ok(Operand::Value(self.known(-128, 255)?))
+    }
+
+    // Octo `:calc` expressions have no precedence and evaluate right to left
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        let value = self.calc_expr()?;
+        self.expect_text("}")?;
+        Ok(value)
+    }
+
+    fn calc_expr(&mut self) -> Result<f64, AsmError> {
+        let lhs = self.calc_term()?;
+
+        let op = match self.peek() {
+            Some("}") | Some(")") | None => return Ok(lhs),
+            Some(_) => self.expect("an operator")?,
+        };
+        let rhs = self.calc_expr()?;
+
+        Ok(match op.text.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => ((lhs as i64) & (rhs as i64)) as f64,
+            "|" => ((lhs as i64) | (rhs as i64)) as f64,
+            "^" => ((lhs as i64) ^ (rhs as i64)) as f64,
+            "<<" => ((lhs as i64) << ((rhs as i64) & 63)) as f64,
+            ">>" => ((lhs as i64) >> ((rhs as i64) & 63)) as f64,
+            "<" => (lhs < rhs) as i64 as f64,
+            ">" => (lhs > rhs) as i64 as f64,
+            "<=" => (lhs <= rhs) as i64 as f64,
+            ">=" => (lhs >= rhs) as i64 as f64,
+            "==" => (lhs == rhs) as i64 as f64,
+            "!=" => (lhs != rhs) as i64 as f64,
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "pow" => lhs.powf(rhs),
+            _ => return error(&op, format!("Unknown operator '{}'", op.text)),
+        })
+    }
+
+    fn calc_term(&mut self) -> Result<f64, AsmError> {
+        let token = self.expect("a value")?;
+
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expr()?;
+                self.expect_text(")")?;
+                Ok(value)
+            }
+            "-" => Ok(-self.calc_term()?),
+            "~" => Ok(!(self.calc_term()? as i64) as f64),
+            "!" => Ok((self.calc_term()? == 0.0) as i64 as f64),
+            "floor" => Ok(self.calc_term()?.floor()),
+            "HERE" => Ok(self.here as f64),
+            text => {
+                if let Some(n) = number(text) {
+                    Ok(n as f64)
+                } else if let Some(n) = self.constants.get(text) {
+                    Ok(*n)
+                } else if let Some(addr) = self.labels.get(text) {
+                    Ok(*addr as f64)
+                } else {
+                    error(&token, format!("Undefined name {}", text))
+                }
+            }
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.expect_register()?;
+        let op = self.expect("a comparison")?;
+
+        match op.text.as_str() {
+            "key" => {
+                return Ok(Condition::Skip(
+                    Instruction::SKNP_Vx(x),
+                    Instruction::SKP_Vx(x),
+                ))
+            }
+            "-key" => {
+                return Ok(Condition::Skip(
+                    Instruction::SKP_Vx(x),
+                    Instruction::SKNP_Vx(x),
+                ))
+            }
+            _ => {}
+        }
+
+        let rhs = self.operand()?;
+
+        // The first instruction skips when the condition is false, the second when it is true
+        Ok(match (op.text.as_str(), rhs) {
+            ("==", Operand::Reg(y)) => {
+                Condition::Skip(Instruction::SNE_Vx_Vy(x, y), Instruction::SE_Vx_Vy(x, y))
+            }
+            ("!=", Operand::Reg(y)) => {
+                Condition::Skip(Instruction::SE_Vx_Vy(x, y), Instruction::SNE_Vx_Vy(x, y))
+            }
+            ("==", Operand::Value(n)) => Condition::Skip(
+                Instruction::SNE_Vx_kk(x, n as u8),
+                Instruction::SE_Vx_kk(x, n as u8),
+            ),
+            ("!=", Operand::Value(n)) => Condition::Skip(
+                Instruction::SE_Vx_kk(x, n as u8),
+                Instruction::SNE_Vx_kk(x, n as u8),
+            ),
+            (op_text @ ("<" | ">" | "<=" | ">="), rhs) => {
+                // VF ends up 1 when `x >= rhs` for GE, or `rhs >= x` for LE
+                let (ge, le) = match rhs {
+                    Operand::Reg(y) => (
+                        vec![
+                            Instruction::LD_Vx_Vy(0xf, x),
+                            Instruction::SUB_Vx_Vy(0xf, y),
+                        ],
+                        vec![
+                            Instruction::LD_Vx_Vy(0xf, x),
+                            Instruction::SUBN_Vx_Vy(0xf, y),
+                        ],
+                    ),
+                    Operand::Value(n) => (
+                        vec![
+                            Instruction::LD_Vx_kk(0xf, n as u8),
+                            Instruction::SUBN_Vx_Vy(0xf, x),
+                        ],
+                        vec![
+                            Instruction::LD_Vx_kk(0xf, n as u8),
+                            Instruction::SUB_Vx_Vy(0xf, x),
+                        ],
+                    ),
+                };
+
+                match op_text {
+                    ">=" => Condition::Compare(ge, 1),
+                    "<" => Condition::Compare(ge, 0),
+                    "<=" => Condition::Compare(le, 1),
+                    _ => Condition::Compare(le, 0),
+                }
+            }
+            _ => return error(&op, format!("Unknown comparison '{}'", op.text)),
+        })
+    }
+
+    // Emit a skip over the next instruction when the condition is false, or true if `negate`
+    fn emit_condition(&mut self, token: &Token, negate: bool) -> Result<(), AsmError> {
+        match self.condition()? {
+            Condition::Skip(when_false, when_true) => {
+                self.emit(token, if negate { when_true } else { when_false })?;
+            }
+            Condition::Compare(setup, flag) => {
+                for inst in setup {
+                    self.emit(token, inst)?;
+                }
+                self.emit(
+                    token,
+                    if negate {
+                        Instruction::SE_Vx_kk(0xf, flag)
+                    } else {
+                        Instruction::SNE_Vx_kk(0xf, flag)
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, token: &Token, name: &Token, addr: u16) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+            return error(name, format!("Duplicate name {}", name.text));
+        }
+        if token.text == ":" && number(&name.text).is_some() {
+            return error(name, "Labels cannot be numbers");
+        }
+        self.labels.insert(name.text.clone(), addr);
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.expect("a label name")?;
+                self.define_label(&token, &name, self.here)?;
+            }
+            ":next" => {
+                let name = self.expect("a label name")?;
+                let Some(addr) = self.here.checked_add(1) else {
+                    return error(&token, "Program does not fit in 64K of memory");
+                };
+                self.define_label(&token, &name, addr)?;
+            }
+            ":const" => {
+                let name = self.expect("a constant name")?;
+                let value = self.known(i64::MIN, i64::MAX)?;
+                self.constants.insert(name.text, value as f64);
+            }
+            ":calc" => {
+                let name = self.expect("a constant name")?;
+                self.expect_text("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.expect("an alias name")?;
+                let x = self.expect_register()?;
+                self.aliases.insert(name.text, x);
+            }
+            ":org" => {
+                let addr = self.known(ORIGIN as i64, 0xffff)?;
+                self.here = addr as u16;
+            }
+            ":byte" => {
+                let n = self.known(-128, 255)?;
+                self.emit_bytes(&token, &[n as u8])?;
+            }
+            ":unpack" => {
+                let kind = self.expect("a nibble or long")?;
+                let high_fixup = match kind.text.as_str() {
+                    "long" => Fixup::UnpackLong,
+                    _ => match number(&kind.text)
+                        .or(self.constants.get(&kind.text).map(|n| *n as i64))
+                    {
+                        Some(n @ 0..=15) => Fixup::UnpackHigh(n as u8),
+                        _ => return error(&kind, "Expected a nibble or long"),
+                    },
+                };
+
+                let (value, target) = self.value()?;
+                let high = self.emit(&token, Instruction::LD_Vx_kk(0, 0))?;
+                let low = self.emit(&token, Instruction::LD_Vx_kk(1, 0))?;
+
+                match value {
+                    Value::Known(n) => {
+                        self.patch(high, high_fixup, n as u16);
+                        self.patch(low, Fixup::UnpackLow, n as u16);
+                    }
+                    Value::Label(_) => {
+                        self.fixups.push((high, high_fixup, target.clone()));
+                        self.fixups.push((low, Fixup::UnpackLow, target));
+                    }
+                }
+            }
+            ":macro" => {
+                let name = self.expect("a macro name")?;
+                let mut args = vec![];
+                loop {
+                    let arg = self.expect("'{'")?;
+                    if arg.text == "{" {
+                        break;
+                    }
+                    args.push(arg.text);
+                }
+
+                let mut body = vec![];
+                let mut depth = 1;
+                loop {
+                    let t = self.expect("'}'")?;
+                    match t.text.as_str() {
+                        "{" => depth += 1,
+                        "}" => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    body.push(t);
+                }
+
+                self.macros.insert(name.text, Macro { args, body });
+            }
+            ":call" => {
+                self.emit_addr(&token, Instruction::CALL_addr, Fixup::Addr, 0xfff)?;
+            }
+
+            "return" | ";" => {
+                self.emit(&token, Instruction::RET)?;
+            }
+            "clear" => {
+                self.emit(&token, Instruction::CLS)?;
+            }
+            "hires" => {
+                self.emit(&token, Instruction::HIRES)?;
+            }
+            "lores" => {
+                self.emit(&token, Instruction::LORES)?;
+            }
+            "exit" => {
+                self.emit(&token, Instruction::EXIT)?;
+            }
+            "scroll-left" => {
+                self.emit(&token, Instruction::SCL)?;
+            }
+            "scroll-right" => {
+                self.emit(&token, Instruction::SCR)?;
+            }
+            "audio" => {
+                self.emit(&token, Instruction::AUDIO)?;
+            }
+            "scroll-down" => {
+                let n = self.known(0, 15)?;
+                self.emit(&token, Instruction::SCD_n(n as u8))?;
+            }
+            "scroll-up" => {
+                let n = self.known(0, 15)?;
+                self.emit(&token, Instruction::SCU_n(n as u8))?;
+            }
+            "plane" => {
+                let n = self.known(0, 3)?;
+                self.emit(&token, Instruction::PLANE_n(n as u8))?;
+            }
+            "native" => {
+                self.emit_addr(&token, Instruction::SYS_addr, Fixup::Addr, 0xfff)?;
+            }
+            "jump" => {
+                self.emit_addr(&token, Instruction::JP_addr, Fixup::Addr, 0xfff)?;
+            }
+            "jump0" => {
+                self.emit_addr(
+                    &token,
+                    |addr| Instruction::JP_Vx_addr((addr >> 8) as u8, addr),
+                    Fixup::Addr,
+                    0xfff,
+                )?;
+            }
+            "bcd" => {
+                let x = self.expect_register()?;
+                self.emit(&token, Instruction::LD_B_Vx(x))?;
+            }
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                let range = if self.peek() == Some("-") {
+                    self.pos += 1;
+                    Some(self.expect_register()?)
+                } else {
+                    None
+                };
+
+                let inst = match (token.text.as_str(), range) {
+                    ("save", None) => Instruction::LD_iI_Vx(x),
+                    (_, None) => Instruction::LD_Vx_iI(x),
+                    ("save", Some(y)) => Instruction::LD_iI_Vx_Vy(x, y),
+                    (_, Some(y)) => Instruction::LD_Vx_Vy_iI(x, y),
+                };
+                self.emit(&token, inst)?;
+            }
+            "saveflags" => {
+                let x = self.expect_register()?;
+                self.emit(&token, Instruction::SAVE_Vx(x))?;
+            }
+            "loadflags" => {
+                let x = self.expect_register()?;
+                self.emit(&token, Instruction::LOAD_Vx(x))?;
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.known(0, 15)?;
+                self.emit(&token, Instruction::DRW_Vx_Vy_n(x, y, n as u8))?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect_text(":=")?;
+                let x = self.expect_register()?;
+                let inst = match token.text.as_str() {
+                    "delay" => Instruction::LD_DT_Vx(x),
+                    "buzzer" => Instruction::LD_ST_Vx(x),
+                    _ => Instruction::PITCH_Vx(x),
+                };
+                self.emit(&token, inst)?;
+            }
+            "i" => {
+                let op = self.expect("an assignment")?;
+                match (op.text.as_str(), self.peek()) {
+                    ("+=", _) => {
+                        let x = self.expect_register()?;
+                        self.emit(&token, Instruction::ADD_I_Vx(x))?;
+                    }
+                    (":=", Some("hex")) | (":=", Some("bighex")) => {
+                        let kind = self.expect("hex")?;
+                        let x = self.expect_register()?;
+                        self.emit(
+                            &token,
+                            if kind.text == "hex" {
+                                Instruction::LD_F_Vx(x)
+                            } else {
+                                Instruction::LD_HF_Vx(x)
+                            },
+                        )?;
+                    }
+                    (":=", Some("long")) => {
+                        self.pos += 1;
+                        self.emit_addr(&token, Instruction::LD_I_long, Fixup::Long, 0xffff)?;
+                    }
+                    (":=", _) => {
+                        self.emit_addr(&token, Instruction::LD_I_addr, Fixup::Addr, 0xfff)?;
+                    }
+                    _ => return error(&op, format!("Unknown operator i {}", op.text)),
+                }
+            }
+
+            "if" => {
+                let is_begin = {
+                    // Look past the condition for `then` or `begin`
+                    let end = self.tokens[self.pos..]
+                        .iter()
+                        .position(|t| t.text == "then" || t.text == "begin");
+                    match end {
+                        Some(offset) => self.tokens[self.pos + offset].text == "begin",
+                        None => return error(&token, "Expected 'then' or 'begin'"),
+                    }
+                };
+
+                self.emit_condition(&token, is_begin)?;
+                self.next();
+
+                if is_begin {
+                    let jump = self.emit(&token, Instruction::JP_addr(0))?;
+                    self.flow.push((Flow::Branch { jump }, token));
+                }
+            }
+            "else" => match self.flow.pop() {
+                Some((Flow::Branch { jump }, start)) => {
+                    let end = self.emit(&token, Instruction::JP_addr(0))?;
+                    self.patch(jump, Fixup::Addr, self.here);
+                    self.flow.push((Flow::Branch { jump: end }, start));
+                }
+                _ => return error(&token, "'else' without 'begin'"),
+            },
+            "end" => match self.flow.pop() {
+                Some((Flow::Branch { jump }, _)) => self.patch(jump, Fixup::Addr, self.here),
+                _ => return error(&token, "'end' without 'begin'"),
+            },
+            "loop" => {
+                self.flow.push((
+                    Flow::Loop {
+                        start: self.here,
+                        breaks: vec![],
+                    },
+                    token,
+                ));
+            }
+            "while" => {
+                self.emit_condition(&token, true)?;
+                let jump = self.emit(&token, Instruction::JP_addr(0))?;
+                match self.flow.iter_mut().rev().find_map(|(f, _)| match f {
+                    Flow::Loop { breaks, .. } => Some(breaks),
+                    _ => None,
+                }) {
+                    Some(breaks) => breaks.push(jump),
+                    None => return error(&token, "'while' outside of a loop"),
+                }
+            }
+            "again" => match self.flow.pop() {
+                Some((Flow::Loop { start, .. }, _)) if start > 0xfff => {
+                    return error(&token, "'loop' is out of 12 bit range");
+                }
+                Some((Flow::Loop { start, breaks }, _)) => {
+                    self.emit(&token, Instruction::JP_addr(start))?;
+                    for jump in breaks {
+                        self.patch(jump, Fixup::Addr, self.here);
+                    }
+                }
+                _ => return error(&token, "'again' without 'loop'"),
+            },
+
+            text if self.macros.contains_key(text) => {
+                self.expansions += 1;
+                if self.expansions > MAX_EXPANSIONS {
+                    return error(&token, "Too many macro expansions");
+                }
+
+                let m = &self.macros[text];
+                let (params, body) = (m.args.clone(), m.body.clone());
+
+                let mut args = HashMap::new();
+                for param in params {
+                    let arg = self.expect("a macro argument")?;
+                    args.insert(param, arg.text);
+                }
+
+                let expanded: Vec<Token> = body
+                    .into_iter()
+                    .map(|t| Token {
+                        text: args.get(&t.text).cloned().unwrap_or(t.text),
+                        // Errors inside a macro point at the invocation
+                        line: token.line,
+                        column: token.column,
+                    })
+                    .collect();
+                self.tokens.splice(self.pos..self.pos, expanded);
+            }
+
+            _ if self.register(&token).is_some() => {
+                let x = self.register(&token).unwrap_or(0);
+                self.assignment(&token, x)?;
+            }
+
+            text if number(text).is_some() || self.constants.contains_key(text) => {
+                self.pos -= 1;
+                let n = self.known(-128, 255)?;
+                self.emit_bytes(&token, &[n as u8])?;
+            }
+
+            "{" => {
+                let n = self.calc()?;
+                if !(-128.0..=255.0).contains(&n) {
+                    return error(&token, format!("Value {} out of range -128..=255", n));
+                }
+                self.emit_bytes(&token, &[n as i64 as u8])?;
+            }
+
+            text if text.starts_with(':') => {
+                return error(&token, format!("Unknown directive {}", text));
+            }
+
+            // A bare name calls the subroutine
+            _ => {
+                self.pos -= 1;
+                self.emit_addr(&token, Instruction::CALL_addr, Fixup::Addr, 0xfff)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn assignment(&mut self, token: &Token, x: u8) -> Result<(), AsmError> {
+        let op = self.expect("an assignment")?;
+
+        let inst = match (op.text.as_str(), self.peek()) {
+            (":=", Some("random")) => {
+                self.pos += 1;
+                Instruction::RND_Vx_kk(x, self.known(0, 255)? as u8)
+            }
+            (":=", Some("key")) => {
+                self.pos += 1;
+                Instruction::LD_Vx_K(x)
+            }
+            (":=", Some("delay")) => {
+                self.pos += 1;
+                Instruction::LD_Vx_DT(x)
+            }
+            (op_text, _) => match (op_text, self.operand()?) {
+                (":=", Operand::Reg(y)) => Instruction::LD_Vx_Vy(x, y),
+                (":=", Operand::Value(n)) => Instruction::LD_Vx_kk(x, n as u8),
+                ("+=", Operand::Reg(y)) => Instruction::ADD_Vx_Vy(x, y),
+                ("+=", Operand::Value(n)) => Instruction::ADD_Vx_kk(x, n as u8),
+                ("-=", Operand::Reg(y)) => Instruction::SUB_Vx_Vy(x, y),
+                ("-=", Operand::Value(n)) => Instruction::ADD_Vx_kk(x, (n as u8).wrapping_neg()),
+                ("=-", Operand::Reg(y)) => Instruction::SUBN_Vx_Vy(x, y),
+                ("|=", Operand::Reg(y)) => Instruction::OR_Vx_Vy(x, y),
+                ("&=", Operand::Reg(y)) => Instruction::AND_Vx_Vy(x, y),
+                ("^=", Operand::Reg(y)) => Instruction::XOR_Vx_Vy(x, y),
+                (">>=", Operand::Reg(y)) => Instruction::SHR_Vx_Vy(x, y),
+                ("<<=", Operand::Reg(y)) => Instruction::SHL_Vx_Vy(x, y),
+                _ => return error(&op, format!("Invalid operands for {}", op.text)),
+            },
+        };
+
+        self.emit(token, inst)?;
+        Ok(())
+    }
+}
+
+pub fn compile(source: &str) -> Result<Program, AsmError> {
+    let mut c = Compiler {
+        tokens: tokenize(source),
+        pos: 0,
+        expansions: 0,
+        image: vec![],
+        here: ORIGIN,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: vec![],
+        flow: vec![],
+    };
+
+    // Execution starts at $200, jump to main unless it is the first thing defined
+    let main_first = matches!(
+        &c.tokens[..],
+        [colon, name, ..] if colon.text == ":" && name.text == "main"
+    );
+    if !main_first {
+        let start = Token {
+            text: "main".to_string(),
+            line: 1,
+            column: 1,
+        };
+        let addr = c.emit(&start, Instruction::JP_addr(0))?;
+        c.fixups.push((addr, Fixup::Addr, start));
+    }
+
+    while let Some(token) = c.next() {
+        c.statement(token)?;
+    }
+
+    if let Some((_, token)) = c.flow.last() {
+        return error(token, format!("Unterminated '{}'", token.text));
+    }
+
+    for (addr, fixup, token) in std::mem::take(&mut c.fixups) {
+        let target = match c.labels.get(&token.text) {
+            Some(target) => *target,
+            None => return error(&token, format!("Undefined name {}", token.text)),
+        };
+
+        if matches!(fixup, Fixup::Addr) && target > 0xfff {
+            return error(&token, format!("{} is out of 12 bit range", token.text));
+        }
+        c.patch(addr, fixup, target);
+    }
+
+    Ok(Program {
+        image: c.image,
+        labels: c.labels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile;
+    use crate::Chip8;
+
+    fn image(source: &str) -> Vec<u8> {
+        compile(source).map_err(|e| e.to_string()).unwrap().image
+    }
+
+    #[test]
+    fn compiles_the_ibm_logo() {
+        let source = include_str!("test_data/2-ibm-logo.8o");
+        assert_eq!(image(source), include_bytes!("test_data/2-ibm-logo.ch8"));
+    }
+
+    #[test]
+    fn jumps_to_main_when_it_is_not_first() {
+        assert_eq!(
+            image(": sub return : main sub loop again"),
+            [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02, 0x12, 0x06]
+        );
+    }
+
+    #[test]
+    fn compiles_constants_aliases_and_calc() {
+        assert_eq!(
+            image(
+                ": main
+                 :const SPEED 3
+                 :alias px v4
+                 :calc DOUBLE { SPEED * 2 + 1 }
+                 px := SPEED
+                 px += DOUBLE
+                 px -= 1
+                 :byte { 1 << 4 }"
+            ),
+            // calc evaluates right to left, so SPEED * (2 + 1)
+            [0x64, 0x03, 0x74, 0x09, 0x74, 0xFF, 0x10]
+        );
+    }
+
+    #[test]
+    fn compiles_conditionals() {
+        assert_eq!(
+            image(
+                ": main
+                 if v1 == 5 then v2 := 1
+                 if v1 key begin
+                   v2 := 2
+                 else
+                   v2 := 3
+                 end"
+            ),
+            [
+                0x41, 0x05, 0x62, 0x01, // if ... then
+                0xE1, 0x9E, 0x12, 0x0C, // skip when pressed, jump to else
+                0x62, 0x02, 0x12, 0x0E, // then branch, jump to end
+                0x62, 0x03, // else branch
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_loops() {
+        assert_eq!(
+            image(
+                ": main
+                 loop
+                   v0 += 1
+                   while v0 != 10
+                 again"
+            ),
+            [0x70, 0x01, 0x40, 0x0A, 0x12, 0x08, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn compiles_relational_conditions() {
+        assert_eq!(
+            image(": main if v1 < v2 then clear"),
+            [0x8F, 0x10, 0x8F, 0x25, 0x4F, 0x00, 0x00, 0xE0]
+        );
+    }
+
+    #[test]
+    fn compiles_macros_next_and_unpack() {
+        let program = compile(
+            ": main
+             :macro twice op { op op }
+             twice clear
+             :next target v0 := 0
+             :unpack 0xA data
+             i := long data
+             : data 1 2",
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.image,
+            [
+                0x00, 0xE0, 0x00, 0xE0, 0x60, 0x00, // twice clear, v0 := 0
+                0x60, 0xA2, 0x61, 0x0E, // unpack
+                0xF0, 0x00, 0x02, 0x0E, // long load
+                0x01, 0x02,
+            ]
+        );
+        assert_eq!(program.labels["target"], 0x205);
+        assert_eq!(program.labels["data"], 0x20E);
+        assert_eq!(program.symbol(0x20E), Some("data"));
+    }
+
+    #[test]
+    fn compiles_xochip_extensions() {
+        assert_eq!(
+            image(": main plane 3 audio pitch := v2 scroll-up 4 save v1 - v3 load v3 - v1"),
+            [0xF3, 0x01, 0xF0, 0x02, 0xF2, 0x3A, 0x00, 0xD4, 0x51, 0x32, 0x53, 0x13]
+        );
+    }
+
+    #[test]
+    fn compiles_org() {
+        assert_eq!(
+            image(": main jump data :org 0x208 : data 0xFF"),
+            [0x12, 0x08, 0, 0, 0, 0, 0, 0, 0xFF]
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        let err = |source| compile(source).err().unwrap().to_string();
+
+        assert_eq!(err(": main\n  jump nowhere"), "2:8: Undefined name nowhere");
+        assert_eq!(err(": main loop"), "1:8: Unterminated 'loop'");
+        assert_eq!(
+            err(": main v0 := 300"),
+            "1:14: Value 300 out of range -128..=255"
+        );
+        assert_eq!(err("clear"), "1:1: Undefined name main");
+        assert_eq!(
+            err(":org 0xfffe\n1 2 3 4"),
+            "2:5: Program does not fit in 64K of memory"
+        );
+        assert!(compile(": main\n:org 0xfffe\n1 2").is_ok());
+        assert_eq!(
+            err(":org 0xffff\n:next last 0"),
+            "2:1: Program does not fit in 64K of memory"
+        );
+    }
+
+    #[test]
+    fn compiled_programs_run() {
+        let program = compile(include_str!("test_data/2-ibm-logo.8o")).unwrap();
+
+        let mut c = Chip8::new();
+        c.load_program(&program.image).unwrap();
+        for _ in 0..30 {
+            c.tick().unwrap();
+        }
+
+        assert_eq!(c.cpu.pc, program.labels["letter-i"] - 2);
+    }
+}
@@ -0,0 +1,315 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use super::instruction::OpcodeSet;
+use super::memory::{FontSet, MEMORY_4K, MEMORY_64K};
+use super::quirks::Quirks;
+use super::timing::{Timing, CLOCK_SPEED_PER_IPF};
+
+// Display sizes as width and height, before and after HIRES
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geometry {
+    pub lores: (usize, usize),
+    pub hires: (usize, usize),
+}
+
+impl Geometry {
+    // Only ever 64x32, HIRES doesn't exist
+    pub const CHIP8: Geometry = Geometry {
+        lores: (64, 32),
+        hires: (64, 32),
+    };
+    pub const SCHIP: Geometry = Geometry {
+        lores: (64, 32),
+        hires: (128, 64),
+    };
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Geometry::SCHIP
+    }
+}
+
+// A machine ROMs were written for, everything the interpreter needs to behave like it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub name: &'static str,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    // Only used by `Timing::Fixed`, `CLOCK_SPEED_PER_IPF` for each instruction a frame
+    pub clock_speed: u32,
+    pub memory_size: usize,
+    pub stack_depth: usize,
+    pub geometry: Geometry,
+    pub font: FontSet,
+    pub opcodes: OpcodeSet,
+}
+
+impl Platform {
+    pub const NAMES: [&'static str; 7] = [
+        "vip",
+        "chip-8-modern",
+        "chip-48",
+        "schip-1.0",
+        "schip-1.1",
+        "schip-modern",
+        "xo-chip",
+    ];
+
+    pub fn cosmac_vip() -> Platform {
+        Platform {
+            name: "vip",
+            quirks: Quirks::chip8(),
+            timing: Timing::Vip,
+            clock_speed: 1_000_000,
+            memory_size: MEMORY_4K,
+            // The interpreter reserves 48 bytes of stack, room for 12 levels of nesting
+            stack_depth: 12,
+            geometry: Geometry::CHIP8,
+            font: FontSet::Chip8,
+            opcodes: OpcodeSet::Chip8,
+        }
+    }
+
+    // CHIP-8 as most modern interpreters run it
+    pub fn chip8_modern() -> Platform {
+        Platform {
+            name: "chip-8-modern",
+            quirks: Quirks {
+                vf_reset: false,
+                display_wait: false,
+                ..Quirks::chip8()
+            },
+            timing: Timing::Fixed,
+            clock_speed: 12 * CLOCK_SPEED_PER_IPF,
+            memory_size: MEMORY_4K,
+            stack_depth: 16,
+            geometry: Geometry::CHIP8,
+            font: FontSet::Chip8,
+            opcodes: OpcodeSet::Chip8,
+        }
+    }
+
+    // The HP48 interpreter, which first shifted Vx in place and jumped with BXNN
+    pub fn chip48() -> Platform {
+        Platform {
+            name: "chip-48",
+            quirks: Quirks {
+                memory: true,
+                memory_increment_by_x: true,
+                lores_half_scroll: false,
+                hires_draw_flag: false,
+                ..Quirks::superchip()
+            },
+            timing: Timing::Fixed,
+            clock_speed: 15 * CLOCK_SPEED_PER_IPF,
+            memory_size: MEMORY_4K,
+            stack_depth: 16,
+            geometry: Geometry::CHIP8,
+            font: FontSet::Chip8,
+            opcodes: OpcodeSet::Chip8,
+        }
+    }
+
+    pub fn schip_1_0() -> Platform {
+        Platform {
+            name: "schip-1.0",
+            quirks: Quirks {
+                memory: true,
+                memory_increment_by_x: true,
+                lores_8x16_sprites: true,
+                lores_half_scroll: false,
+                ..Quirks::superchip()
+            },
+            timing: Timing::Fixed,
+            clock_speed: 30 * CLOCK_SPEED_PER_IPF,
+            memory_size: MEMORY_4K,
+            stack_depth: 16,
+            geometry: Geometry::SCHIP,
+            font: FontSet::SuperChip,
+            opcodes: OpcodeSet::SuperChip10,
+        }
+    }
+
+    pub fn schip_1_1() -> Platform {
+        Platform {
+            name: "schip-1.1",
+            quirks: Quirks::superchip(),
+            timing: Timing::Fixed,
+            clock_speed: 30 * CLOCK_SPEED_PER_IPF,
+            memory_size: MEMORY_4K,
+            stack_depth: 16,
+            geometry: Geometry::SCHIP,
+            font: FontSet::SuperChip,
+            opcodes: OpcodeSet::SuperChip11,
+        }
+    }
+
+    // SCHIP as Octo and SCHPC run it
+    pub fn schip_modern() -> Platform {
+        Platform {
+            name: "schip-modern",
+            quirks: Quirks {
+                hires_draw_flag: false,
+                lores_half_scroll: false,
+                resolution_clears: true,
+                ..Quirks::superchip()
+            },
+            timing: Timing::Fixed,
+            clock_speed: 30 * CLOCK_SPEED_PER_IPF,
+            memory_size: MEMORY_4K,
+            stack_depth: 16,
+            geometry: Geometry::SCHIP,
+            font: FontSet::Octo,
+            opcodes: OpcodeSet::SuperChip11,
+        }
+    }
+
+    pub fn xochip() -> Platform {
+        Platform {
+            name: "xo-chip",
+            quirks: Quirks::xochip(),
+            timing: Timing::Fixed,
+            clock_speed: 1000 * CLOCK_SPEED_PER_IPF,
+            memory_size: MEMORY_64K,
+            stack_depth: 16,
+            geometry: Geometry::SCHIP,
+            font: FontSet::Octo,
+            opcodes: OpcodeSet::XoChip,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPlatform(pub String);
+
+impl fmt::Display for UnknownPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown platform {}, expected one of {}",
+            self.0,
+            Platform::NAMES.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownPlatform {}
+
+impl FromStr for Platform {
+    type Err = UnknownPlatform;
+
+    fn from_str(s: &str) -> Result<Platform, UnknownPlatform> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "vip" | "cosmac-vip" | "chip-8" | "chip8" => Ok(Platform::cosmac_vip()),
+            "chip-8-modern" | "chip8-modern" => Ok(Platform::chip8_modern()),
+            "chip-48" | "chip48" => Ok(Platform::chip48()),
+            "schip-1.0" | "schip1.0" | "superchip-1.0" => Ok(Platform::schip_1_0()),
+            "schip-1.1" | "schip1.1" | "superchip-1.1" | "schip" | "superchip" => {
+                Ok(Platform::schip_1_1())
+            }
+            "schip-modern" | "modern-schip" | "schpc" => Ok(Platform::schip_modern()),
+            "xo-chip" | "xochip" => Ok(Platform::xochip()),
+            _ => Err(UnknownPlatform(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::fault::Fault;
+    use crate::instruction::Instruction;
+    use crate::Chip8;
+
+    #[test]
+    fn platforms_parse_from_their_names() {
+        for name in Platform::NAMES {
+            assert_eq!(name.parse::<Platform>().unwrap().name, name);
+        }
+        assert_eq!("SCHIP_1.1".parse(), Ok(Platform::schip_1_1()));
+        assert_eq!(
+            "megachip".parse::<Platform>(),
+            Err(UnknownPlatform("megachip".to_string()))
+        );
+    }
+
+    #[test]
+    fn platforms_only_allow_their_opcodes() {
+        let mut cpu = CPU::new();
+        cpu.set_platform(&Platform::schip_1_0()).unwrap();
+        cpu.memory.load_program(&[0x00, 0xff, 0x00, 0xfb]).unwrap(); // HIRES, SCR
+
+        assert!(cpu.step().is_ok());
+        assert!(cpu.hires);
+        assert_eq!(
+            cpu.step(),
+            Err(Fault::InvalidOpcode {
+                op: 0x00fb,
+                addr: 0x202
+            })
+        );
+    }
+
+    #[test]
+    fn platforms_run_their_instructions_per_frame() {
+        for (platform, ipf) in [
+            (Platform::chip8_modern(), 12),
+            (Platform::chip48(), 15),
+            (Platform::schip_1_0(), 30),
+            (Platform::schip_1_1(), 30),
+            (Platform::schip_modern(), 30),
+            (Platform::xochip(), 1000),
+        ] {
+            let mut chip8 = Chip8::new();
+            chip8.set_platform(&platform).unwrap();
+            // ADD V0, 1 all the way through memory
+            chip8.load_program(&[0x70, 0x01].repeat(0x700)).unwrap();
+
+            chip8.tick().unwrap();
+            assert_eq!((chip8.cpu.pc - 0x200) / 2, ipf, "{}", platform);
+        }
+    }
+
+    #[test]
+    fn platforms_set_the_display_geometry() {
+        let mut cpu = CPU::new();
+        cpu.set_platform(&Platform::schip_1_1()).unwrap();
+        cpu.execute(Instruction::HIRES);
+        assert_eq!((cpu.width, cpu.height), (128, 64));
+
+        // Switching platforms starts over in lores
+        cpu.set_platform(&Platform::cosmac_vip()).unwrap();
+        assert_eq!(cpu.geometry, Geometry::CHIP8);
+        assert!(!cpu.hires);
+        assert_eq!((cpu.width, cpu.height), (64, 32));
+        assert_eq!(cpu.vram.len(), 64 * 32);
+
+        // Executed anyway, HIRES has nowhere bigger to go
+        cpu.execute(Instruction::HIRES);
+        assert_eq!((cpu.width, cpu.height), (64, 32));
+    }
+
+    #[test]
+    fn platforms_configure_the_machine() {
+        let mut cpu = CPU::new();
+        cpu.set_platform(&Platform::cosmac_vip()).unwrap();
+
+        assert_eq!(cpu.timing, Timing::Vip);
+        assert_eq!(cpu.quirks, Quirks::chip8());
+        assert_eq!(cpu.memory.memory[0x050], 0, "The VIP has no big font");
+
+        cpu.set_platform(&Platform::xochip()).unwrap();
+        assert_eq!(cpu.memory.size(), MEMORY_64K);
+        assert_eq!(cpu.memory.memory[0x050], 0x3c);
+    }
+}
@@ -1,6 +1,6 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Quirks {
     pub vf_reset: bool,
     pub memory: bool,
@@ -9,6 +9,18 @@ pub struct Quirks {
     pub hires_draw_flag: bool,
     pub shifting: bool,
     pub jumping: bool,
+    // SCHIP 1.1 scrolls by hires pixels, so half the distance in lores
+    #[serde(default)]
+    pub lores_half_scroll: bool,
+    // SCHIP 1.0 draws DXY0 as an 8x16 sprite in lores
+    #[serde(default)]
+    pub lores_8x16_sprites: bool,
+    // SCHIP 1.0 leaves I pointing at the last register loaded or stored
+    #[serde(default)]
+    pub memory_increment_by_x: bool,
+    // HIRES and LORES clear the display
+    #[serde(default)]
+    pub resolution_clears: bool,
 }
 
 impl Quirks {
@@ -29,6 +41,10 @@ impl Quirks {
             hires_draw_flag,
             shifting,
             jumping,
+            lores_half_scroll: false,
+            lores_8x16_sprites: false,
+            memory_increment_by_x: false,
+            resolution_clears: false,
         }
     }
 
@@ -41,6 +57,10 @@ impl Quirks {
             hires_draw_flag: false,
             shifting: false,
             jumping: false,
+            lores_half_scroll: false,
+            lores_8x16_sprites: false,
+            memory_increment_by_x: false,
+            resolution_clears: false,
         }
     }
 
@@ -53,6 +73,10 @@ impl Quirks {
             hires_draw_flag: true,
             shifting: true,
             jumping: true,
+            lores_half_scroll: true,
+            lores_8x16_sprites: false,
+            memory_increment_by_x: false,
+            resolution_clears: false,
         }
     }
 
@@ -65,6 +89,10 @@ impl Quirks {
             hires_draw_flag: false,
             shifting: false,
             jumping: false,
+            lores_half_scroll: false,
+            lores_8x16_sprites: false,
+            memory_increment_by_x: false,
+            resolution_clears: true,
         }
     }
 }
@@ -0,0 +1,202 @@
+use super::{
+    cpu::CPU,
+    screenshot::{self, Color, DEFAULT_PALETTE},
+};
+
+pub type Rgba = [u8; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scaling {
+    // The largest whole multiple of the display that fits, so every pixel is the same size
+    #[default]
+    Integer,
+    // As large as fits while keeping the aspect ratio
+    Fit,
+}
+
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    // Two colours show every plane in the foreground, four give each XO-CHIP plane its own
+    pub palette: Vec<Color>,
+    pub scaling: Scaling,
+    // Drawn along the top and left edge of each display pixel
+    pub grid: Option<Color>,
+    // Fills the area around the display
+    pub border: Color,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new(&DEFAULT_PALETTE)
+    }
+}
+
+fn rgba(color: Color) -> Rgba {
+    [color[0], color[1], color[2], 0xff]
+}
+
+impl Renderer {
+    pub fn new(palette: &[Color]) -> Renderer {
+        let palette = screenshot::usable(palette);
+        Renderer {
+            palette: palette.to_vec(),
+            scaling: Scaling::default(),
+            grid: None,
+            border: palette[0],
+        }
+    }
+
+    // Size of the display inside a `width` x `height` buffer
+    pub fn display_size(&self, cpu: &CPU, width: usize, height: usize) -> (usize, usize) {
+        match self.scaling {
+            Scaling::Integer => {
+                let scale = (width / cpu.width).min(height / cpu.height).max(1);
+                (cpu.width * scale, cpu.height * scale)
+            }
+            Scaling::Fit => {
+                if width * cpu.height > height * cpu.width {
+                    (height * cpu.width / cpu.height, height)
+                } else {
+                    (width, width * cpu.height / cpu.width)
+                }
+            }
+        }
+    }
+
+    pub fn render(&self, cpu: &CPU, width: usize, height: usize) -> Vec<u8> {
+        let mut buffer = vec![0; width * height * 4];
+        self.render_into(cpu, &mut buffer, width, height);
+        buffer
+    }
+
+    // Render RGBA8 pixels into `buffer`, which holds `width` x `height` of them
+    pub fn render_into(&self, cpu: &CPU, buffer: &mut [u8], width: usize, height: usize) {
+        assert_eq!(
+            buffer.len(),
+            width * height * 4,
+            "Buffer size does not match"
+        );
+
+        let (dw, dh) = self.display_size(cpu, width, height);
+        // Centred, or clipped at the top left when the buffer is too small
+        let ox = width.saturating_sub(dw) / 2;
+        let oy = height.saturating_sub(dh) / 2;
+
+        let colors: Vec<Rgba> = (0..4)
+            .map(|i| rgba(screenshot::color(&self.palette, i)))
+            .collect();
+        let border = rgba(self.border);
+        let grid = self.grid.map(rgba);
+
+        // Display column of each buffer column, None outside the display
+        let source_x: Vec<Option<usize>> = (0..width)
+            .map(|x| {
+                (ox..ox + dw)
+                    .contains(&x)
+                    .then(|| (x - ox) * cpu.width / dw)
+            })
+            .collect();
+
+        for (y, row) in buffer.chunks_exact_mut(width * 4).enumerate() {
+            if !(oy..oy + dh).contains(&y) {
+                row.chunks_exact_mut(4)
+                    .for_each(|p| p.copy_from_slice(&border));
+                continue;
+            }
+
+            let sy = (y - oy) * cpu.height / dh;
+            let top_edge = y > oy && (y - 1 - oy) * cpu.height / dh != sy;
+            let line = &cpu.vram[sy * cpu.width..(sy + 1) * cpu.width];
+
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let color = match source_x[x] {
+                    None => border,
+                    Some(sx) => {
+                        let left_edge = x > ox && source_x[x - 1] != Some(sx);
+                        match grid {
+                            Some(grid) if top_edge || left_edge => grid,
+                            _ => colors[(line[sx] & 3) as usize],
+                        }
+                    }
+                };
+                pixel.copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Instruction;
+
+    fn pixel(buffer: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+        &buffer[(y * width + x) * 4..(y * width + x) * 4 + 4]
+    }
+
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.vram[0] = 1;
+        cpu.vram[65] = 3;
+        cpu
+    }
+
+    #[test]
+    fn integer_scaling_centres_the_display() {
+        let renderer = Renderer::default();
+        let buffer = renderer.render(&cpu(), 200, 100);
+
+        // Scale 3, so 192 x 96 starting at (4, 2)
+        assert_eq!(renderer.display_size(&cpu(), 200, 100), (192, 96));
+        assert_eq!(pixel(&buffer, 200, 3, 2), [0, 0, 0, 0xff]);
+        assert_eq!(pixel(&buffer, 200, 4, 2), [255, 255, 255, 0xff]);
+        assert_eq!(pixel(&buffer, 200, 6, 4), [255, 255, 255, 0xff]);
+        assert_eq!(pixel(&buffer, 200, 7, 5), [85, 85, 85, 0xff]);
+    }
+
+    #[test]
+    fn fit_scaling_fills_one_side() {
+        let renderer = Renderer {
+            scaling: Scaling::Fit,
+            ..Default::default()
+        };
+
+        assert_eq!(renderer.display_size(&cpu(), 200, 100), (200, 100));
+        assert_eq!(renderer.display_size(&cpu(), 300, 100), (200, 100));
+        assert_eq!(renderer.display_size(&cpu(), 100, 100), (100, 50));
+    }
+
+    #[test]
+    fn two_colour_palettes_merge_planes() {
+        let renderer = Renderer::new(&[[0, 0, 0], [0, 255, 0]]);
+        let buffer = renderer.render(&cpu(), 64, 32);
+
+        assert_eq!(pixel(&buffer, 64, 0, 0), [0, 255, 0, 0xff]);
+        assert_eq!(pixel(&buffer, 64, 1, 1), [0, 255, 0, 0xff]);
+        assert_eq!(pixel(&buffer, 64, 2, 2), [0, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn short_palettes_fall_back_to_the_default() {
+        assert_eq!(Renderer::new(&[]).palette, DEFAULT_PALETTE);
+        assert_eq!(Renderer::new(&[[9, 9, 9]]).border, [0, 0, 0]);
+    }
+
+    #[test]
+    fn grid_lines_separate_pixels() {
+        let renderer = Renderer {
+            grid: Some([1, 2, 3]),
+            ..Default::default()
+        };
+
+        let mut cpu = cpu();
+        cpu.execute(Instruction::HIRES);
+        cpu.vram.fill(1);
+
+        let buffer = renderer.render(&cpu, 512, 256);
+        assert_eq!(pixel(&buffer, 512, 0, 0), [255, 255, 255, 0xff]);
+        assert_eq!(pixel(&buffer, 512, 4, 1), [1, 2, 3, 0xff]);
+        assert_eq!(pixel(&buffer, 512, 1, 4), [1, 2, 3, 0xff]);
+        assert_eq!(pixel(&buffer, 512, 5, 5), [255, 255, 255, 0xff]);
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+
+use super::cpu::CPU;
+
+pub const DEFAULT_BUDGET: usize = 4 * 1024 * 1024;
+pub const FRAMES_PER_SECOND: f32 = 60.0;
+
+// Keeps the newest snapshot in full, and a delta back to each older one
+pub struct Rewind {
+    pub interval: u32,
+    pub budget: usize,
+
+    frames_since: u32,
+    latest: Option<Vec<u8>>,
+    history: VecDeque<Vec<u8>>,
+    bytes: usize,
+}
+
+impl Rewind {
+    pub fn new(interval: u32, budget: usize) -> Rewind {
+        Rewind {
+            interval: interval.max(1),
+            budget,
+            frames_since: 0,
+            latest: None,
+            history: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+
+    // Number of snapshots that can be rewound to
+    pub fn len(&self) -> usize {
+        self.history.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    // Memory used by the snapshots
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.frames_since = 0;
+        self.latest = None;
+        self.history.clear();
+        self.bytes = 0;
+    }
+
+    // Called once per frame, snapshots every `interval` frames
+    pub fn record(&mut self, cpu: &CPU) {
+        if self.latest.is_some() {
+            self.frames_since += 1;
+            if self.frames_since < self.interval {
+                return;
+            }
+        }
+        self.frames_since = 0;
+
+        let state = bincode::serialize(cpu).expect("CPU always serializes");
+        if let Some(previous) = self.latest.take() {
+            let delta = delta(&state, &previous);
+            self.bytes += delta.len();
+            self.bytes -= previous.len();
+            self.history.push_back(delta);
+        }
+        self.bytes += state.len();
+        self.latest = Some(state);
+
+        while self.bytes > self.budget {
+            match self.history.pop_front() {
+                Some(oldest) => self.bytes -= oldest.len(),
+                None => break,
+            }
+        }
+    }
+
+    // Go back `snapshots` snapshots, counting the newest as one if frames have run since
+    pub fn back(&mut self, snapshots: usize) -> Option<CPU> {
+        let mut state = self.latest.take()?;
+
+        let steps = if self.frames_since > 0 {
+            snapshots.saturating_sub(1)
+        } else {
+            snapshots
+        };
+        for _ in 0..steps {
+            match self.history.pop_back() {
+                Some(delta) => {
+                    self.bytes -= delta.len();
+                    self.bytes -= state.len();
+                    state = apply(&state, &delta);
+                    self.bytes += state.len();
+                }
+                None => break,
+            }
+        }
+
+        self.frames_since = 0;
+        let cpu = bincode::deserialize(&state).expect("Snapshots always deserialize");
+        self.latest = Some(state);
+        Some(cpu)
+    }
+
+    pub fn back_seconds(&mut self, seconds: f32) -> Option<CPU> {
+        let snapshots = (seconds * FRAMES_PER_SECOND / self.interval as f32).round() as usize;
+        self.back(snapshots.max(1))
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Rewind::new(1, DEFAULT_BUDGET)
+    }
+}
+
+fn push_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        n |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
+// Delta turning `from` into `to`: the length of `to`, then runs of
+// (unchanged count, changed count, changed bytes XORed with `from`)
+fn delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let len = from.len().max(to.len());
+    let xor = |i: usize| from.get(i).unwrap_or(&0) ^ to.get(i).unwrap_or(&0);
+
+    let mut out = vec![];
+    push_varint(&mut out, to.len());
+
+    let mut i = 0;
+    while i < len {
+        let start = i;
+        while i < len && xor(i) == 0 {
+            i += 1;
+        }
+        let same = i - start;
+
+        let start = i;
+        while i < len && xor(i) != 0 {
+            i += 1;
+        }
+        push_varint(&mut out, same);
+        push_varint(&mut out, i - start);
+        out.extend((start..i).map(xor));
+    }
+
+    out
+}
+
+fn apply(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+
+    let mut out = from.to_vec();
+    out.resize(len.max(from.len()), 0);
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let changed = read_varint(delta, &mut pos);
+        for _ in 0..changed {
+            out[i] ^= delta[pos];
+            i += 1;
+            pos += 1;
+        }
+    }
+
+    out.truncate(len);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas_round_trip() {
+        let a = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let mut b = a.clone();
+        b[2] = 9;
+        b[6] = 0;
+
+        assert_eq!(apply(&a, &delta(&a, &b)), b);
+        assert_eq!(apply(&b, &delta(&b, &a)), a);
+
+        let longer: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        assert_eq!(apply(&a, &delta(&a, &longer)), longer);
+        assert_eq!(apply(&longer, &delta(&longer, &a)), a);
+
+        let mut changed = longer.clone();
+        changed[100] = 0;
+        assert!(delta(&longer, &changed).len() < 10);
+    }
+
+    #[test]
+    fn snapshots_are_taken_every_interval() {
+        let mut rewind = Rewind::new(3, DEFAULT_BUDGET);
+        let mut cpu = CPU::new();
+
+        for frame in 0..10 {
+            cpu.pc = 0x200 + frame * 2;
+            rewind.record(&cpu);
+        }
+
+        // Frames 0, 3, 6 and 9
+        assert_eq!(rewind.len(), 4);
+        assert_eq!(rewind.back(1).map(|cpu| cpu.pc), Some(0x20c));
+        assert_eq!(rewind.back(1).map(|cpu| cpu.pc), Some(0x206));
+        assert_eq!(rewind.back(10).map(|cpu| cpu.pc), Some(0x200));
+        assert_eq!(rewind.len(), 1);
+    }
+
+    #[test]
+    fn history_stays_within_the_budget() {
+        let full = bincode::serialize(&CPU::new()).unwrap().len();
+        let mut rewind = Rewind::new(1, full + 100);
+        let mut cpu = CPU::new();
+
+        for frame in 0..100 {
+            cpu.pc = 0x200 + frame * 2;
+            cpu.vram[frame as usize] = 1;
+            rewind.record(&cpu);
+            assert!(rewind.bytes() <= full + 100);
+        }
+
+        assert!(rewind.len() > 1 && rewind.len() < 100);
+    }
+}
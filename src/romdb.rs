@@ -0,0 +1,265 @@
+use core::fmt;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{
+    config::{parse_color, Config, QuirkOverrides},
+    movie::sha1_hex,
+    platform::Platform,
+    screenshot::Color,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomDatabaseError {
+    Read(String),
+    Decode(String),
+}
+
+impl fmt::Display for RomDatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomDatabaseError::Read(err) => write!(f, "Unable to read ROM database: {}", err),
+            RomDatabaseError::Decode(err) => write!(f, "Invalid ROM database: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RomDatabaseError {}
+
+// What the database knows about one ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: String,
+    // Database platform ids the ROM runs on, in order of preference
+    pub platforms: Vec<String>,
+    // Platform, quirks, tickrate and colours for the first platform we emulate
+    pub config: Config,
+    // Keypad keys for "up", "down", "a" and the like
+    pub keys: HashMap<String, u8>,
+}
+
+// `programs.json` from the community CHIP-8 database, its `sha1-hashes.json` only
+// indexes the same ROMs so it isn't needed
+#[derive(Debug, Default)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>,
+}
+
+#[derive(Deserialize)]
+struct ProgramEntry {
+    title: String,
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<u32>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkEntry>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+    colors: Option<ColorsEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkEntry {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ColorsEntry {
+    pixels: Option<Vec<String>>,
+}
+
+// The database's platform ids, None for machines we can't emulate
+fn platform(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some(Platform::cosmac_vip()),
+        "modernChip8" => Some(Platform::chip8_modern()),
+        "chip48" => Some(Platform::chip48()),
+        "superchip1" => Some(Platform::schip_1_0()),
+        "superchip" => Some(Platform::schip_1_1()),
+        "xochip" => Some(Platform::xochip()),
+        _ => None,
+    }
+}
+
+impl QuirkEntry {
+    fn overrides(&self) -> QuirkOverrides {
+        QuirkOverrides {
+            vf_reset: self.logic,
+            // Incrementing by X still means I moves
+            memory: match (self.memory_leave_i_unchanged, self.memory_increment_by_x) {
+                (Some(unchanged), _) => Some(!unchanged),
+                (None, Some(true)) => Some(true),
+                _ => None,
+            },
+            display_wait: self.vblank,
+            sprite_wrapping: self.wrap,
+            shifting: self.shift,
+            jumping: self.jump,
+            memory_increment_by_x: self.memory_increment_by_x,
+            ..QuirkOverrides::default()
+        }
+    }
+}
+
+impl RomEntry {
+    fn info(self, title: &str) -> RomInfo {
+        let (platform, quirks) = self
+            .platforms
+            .iter()
+            .find_map(|id| platform(id).map(|platform| (platform, id)))
+            .map(|(platform, id)| {
+                let quirks = self.quirky_platforms.get(id).map(QuirkEntry::overrides);
+                (Some(platform), quirks.unwrap_or_default())
+            })
+            .unwrap_or_default();
+
+        // Like a config file's, a palette needs a background and a foreground
+        let palette = self
+            .colors
+            .and_then(|colors| colors.pixels)
+            .and_then(|pixels| pixels.iter().map(|s| parse_color(s)).collect())
+            .filter(|palette: &Vec<Color>| palette.len() >= 2);
+
+        RomInfo {
+            title: title.to_string(),
+            platforms: self.platforms,
+            config: Config {
+                platform,
+                quirks,
+                ipf: self.tickrate,
+                palette,
+                ..Config::default()
+            },
+            keys: self.keys,
+        }
+    }
+}
+
+impl RomDatabase {
+    pub fn from_json(s: &str) -> Result<RomDatabase, RomDatabaseError> {
+        let programs: Vec<ProgramEntry> =
+            serde_json::from_str(s).map_err(|err| RomDatabaseError::Decode(err.to_string()))?;
+
+        let mut roms = HashMap::new();
+        for program in programs {
+            for (sha1, rom) in program.roms {
+                roms.insert(sha1.to_ascii_lowercase(), rom.info(&program.title));
+            }
+        }
+
+        Ok(RomDatabase { roms })
+    }
+
+    pub fn load(path: &Path) -> Result<RomDatabase, RomDatabaseError> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|err| RomDatabaseError::Read(format!("{}: {}", path.display(), err)))?;
+        RomDatabase::from_json(&s)
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn lookup(&self, program: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&sha1_hex(program))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+    use crate::Chip8;
+
+    fn database() -> RomDatabase {
+        RomDatabase::from_json(include_str!("test_data/programs.json")).unwrap()
+    }
+
+    #[test]
+    fn roms_are_found_by_hash() {
+        let db = database();
+        assert_eq!(db.len(), 2);
+
+        let info = db
+            .lookup(include_bytes!("test_data/2-ibm-logo.ch8"))
+            .unwrap();
+        assert_eq!(info.title, "IBM Logo");
+        assert_eq!(info.config.platform, Some(Platform::cosmac_vip()));
+        assert_eq!(info.config.ipf, Some(10));
+
+        assert!(db
+            .lookup(include_bytes!("test_data/3-corax+.ch8"))
+            .is_none());
+    }
+
+    #[test]
+    fn unsupported_platforms_are_skipped() {
+        let db = database();
+        let info = db.lookup(include_bytes!("test_data/6-keypad.ch8")).unwrap();
+
+        assert_eq!(info.platforms[0], "megachip8");
+        assert_eq!(info.config.platform, Some(Platform::schip_1_1()));
+        assert_eq!(info.config.palette, Some(vec![[0x22; 3], [0xe0; 3]]));
+        assert_eq!(info.keys.get("up"), Some(&5));
+    }
+
+    #[test]
+    fn chip8_configures_itself_from_a_match() {
+        let program = include_bytes!("test_data/6-keypad.ch8");
+        let mut chip8 = Chip8::new();
+
+        let title = chip8
+            .configure_from(&database(), program)
+            .map(|info| info.title.clone());
+        assert_eq!(title, Some("Keypad test".to_string()));
+        assert_eq!(
+            chip8.cpu.quirks,
+            Quirks {
+                shifting: false,
+                memory: true,
+                memory_increment_by_x: true,
+                ..Quirks::superchip()
+            }
+        );
+
+        // ADD V0, 1 all the way through memory
+        chip8.load_program(&[0x70, 0x01].repeat(0x700)).unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(
+            chip8.cpu.pc,
+            0x200 + 20 * 2,
+            "The tickrate is instructions per frame"
+        );
+    }
+
+    #[test]
+    fn short_palettes_are_dropped() {
+        let db = RomDatabase::from_json(
+            r#"[{ "title": "Empty", "roms": { "da39a3ee5e6b4b0d3255bfef95601890afd80709": {
+                "colors": { "pixels": [] }
+            } } }]"#,
+        )
+        .unwrap();
+
+        assert_eq!(db.lookup(&[]).unwrap().config.palette, None);
+    }
+}
@@ -0,0 +1,238 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+use super::Chip8;
+
+// Bumped whenever the layout of the machine changes, old states are rejected
+pub const VERSION: u32 = 3;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    BadMagic,
+    VersionMismatch { expected: u32, found: u32 },
+    Decode(String),
+    // Decoded fine, but describes a machine that can't exist
+    Invalid(String),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "Not a save state"),
+            SaveStateError::VersionMismatch { expected, found } => write!(
+                f,
+                "Save state version {} does not match version {}",
+                found, expected
+            ),
+            SaveStateError::Decode(err) => write!(f, "Corrupt save state: {}", err),
+            SaveStateError::Invalid(err) => write!(f, "Invalid save state: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+#[derive(Serialize)]
+struct SaveStateRef<'a> {
+    version: u32,
+    chip8: &'a Chip8,
+}
+
+// The version is checked separately, before decoding the machine
+#[derive(Deserialize)]
+struct SaveState {
+    chip8: Chip8,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+fn check_version(found: u32) -> Result<(), SaveStateError> {
+    if found != VERSION {
+        return Err(SaveStateError::VersionMismatch {
+            expected: VERSION,
+            found,
+        });
+    }
+    Ok(())
+}
+
+fn check_machine(chip8: Chip8) -> Result<Chip8, SaveStateError> {
+    chip8.cpu.validate().map_err(SaveStateError::Invalid)?;
+    Ok(chip8)
+}
+
+// MAGIC, then the version as a little endian u32, then the bincode encoded machine
+pub fn to_bytes(chip8: &Chip8) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend(bincode::serialize(chip8).expect("Chip8 always serializes"));
+    bytes
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<Chip8, SaveStateError> {
+    if bytes.len() < 8 || &bytes[0..4] != MAGIC {
+        return Err(SaveStateError::BadMagic);
+    }
+    check_version(u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]))?;
+
+    let chip8 =
+        bincode::deserialize(&bytes[8..]).map_err(|err| SaveStateError::Decode(err.to_string()))?;
+    check_machine(chip8)
+}
+
+pub fn to_json(chip8: &Chip8) -> String {
+    serde_json::to_string(&SaveStateRef {
+        version: VERSION,
+        chip8,
+    })
+    .expect("Chip8 always serializes")
+}
+
+pub fn from_json(json: &str) -> Result<Chip8, SaveStateError> {
+    // Check the version first, so a mismatch isn't reported as a decode error
+    let version: Version =
+        serde_json::from_str(json).map_err(|err| SaveStateError::Decode(err.to_string()))?;
+    check_version(version.version)?;
+
+    let state: SaveState =
+        serde_json::from_str(json).map_err(|err| SaveStateError::Decode(err.to_string()))?;
+    check_machine(state.chip8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fault::FaultPolicy, instruction::Instruction, quirks::Quirks, timing::Timing};
+    use serde_json::{json, Value};
+
+    // Corax+ with a few frames run, then some state that isn't the default
+    fn running_chip8() -> Chip8 {
+        let mut c = Chip8::new();
+        c.seed(7);
+        c.cpu.quirks = Quirks::superchip();
+        c.cpu.timing = Timing::Vip;
+        c.load_program(include_bytes!("test_data/3-corax+.ch8"))
+            .unwrap();
+        for _ in 0..3 {
+            c.tick().unwrap();
+        }
+        c.set_breakpoint(0x300);
+        c
+    }
+
+    fn assert_same_machine(a: &mut Chip8, b: &mut Chip8) {
+        assert_eq!(
+            serde_json::to_string(a).unwrap(),
+            serde_json::to_string(b).unwrap()
+        );
+
+        // Both should carry on identically, including the random numbers
+        for _ in 0..20 {
+            a.tick().unwrap();
+            b.tick().unwrap();
+            a.cpu
+                .execute(crate::instruction::Instruction::RND_Vx_kk(0, 0xff));
+            b.cpu
+                .execute(crate::instruction::Instruction::RND_Vx_kk(0, 0xff));
+        }
+        assert_eq!(
+            serde_json::to_string(a).unwrap(),
+            serde_json::to_string(b).unwrap()
+        );
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mut a = running_chip8();
+        let mut b = from_bytes(&to_bytes(&a)).unwrap();
+
+        assert!(b.cpu.quirks.jumping);
+        assert_eq!(b.cpu.timing, Timing::Vip);
+        assert_same_machine(&mut a, &mut b);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut a = running_chip8();
+        let mut b = from_json(&to_json(&a)).unwrap();
+
+        assert_same_machine(&mut a, &mut b);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let chip8 = running_chip8();
+
+        let mut bytes = to_bytes(&chip8);
+        bytes[4] = 99;
+        assert_eq!(
+            from_bytes(&bytes).err(),
+            Some(SaveStateError::VersionMismatch {
+                expected: VERSION,
+                found: 99
+            })
+        );
+        assert_eq!(from_bytes(b"nope").err(), Some(SaveStateError::BadMagic));
+
+        let json =
+            to_json(&chip8).replacen(&format!("\"version\":{}", VERSION), "\"version\":99", 1);
+        assert_eq!(
+            from_json(&json).err(),
+            Some(SaveStateError::VersionMismatch {
+                expected: VERSION,
+                found: 99
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_impossible_machines() {
+        let state: Value = serde_json::from_str(&to_json(&running_chip8())).unwrap();
+        let tampered = |pointer: &str, replacement: Value| {
+            let mut state = state.clone();
+            *state.pointer_mut(pointer).unwrap() = replacement;
+            from_json(&state.to_string())
+        };
+
+        for (pointer, replacement) in [
+            ("/chip8/cpu/vram", json!(vec![0; 10])),
+            ("/chip8/cpu/width", json!(0)),
+            ("/chip8/cpu/memory/memory", json!(vec![0; 16])),
+            ("/chip8/cpu/plane", json!(4)),
+        ] {
+            assert!(
+                matches!(
+                    tampered(pointer, replacement),
+                    Err(SaveStateError::Invalid(_))
+                ),
+                "{}",
+                pointer
+            );
+        }
+        assert!(tampered("/chip8/cpu/plane", json!(3)).is_ok());
+
+        // Halting keeps the stack within its depth, so a deeper one was crafted
+        let mut chip8 = running_chip8();
+        chip8.cpu.faults.stack_overflow = FaultPolicy::Halt;
+        chip8.cpu.execute(Instruction::CALL_addr(0x300));
+        chip8.cpu.stack_depth = 0;
+        assert!(matches!(
+            from_bytes(&to_bytes(&chip8)),
+            Err(SaveStateError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_states() {
+        let bytes = to_bytes(&running_chip8());
+        assert!(matches!(
+            from_bytes(&bytes[..bytes.len() / 2]),
+            Err(SaveStateError::Decode(_))
+        ));
+    }
+}
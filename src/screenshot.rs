@@ -0,0 +1,234 @@
+use std::{fmt::Write, io, path::Path};
+
+use super::cpu::CPU;
+
+pub type Color = [u8; 3];
+
+// Background, plane 1, plane 2, both planes
+pub const DEFAULT_PALETTE: [Color; 4] = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Pbm,
+    Png,
+    Svg,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "pbm" => Some(Format::Pbm),
+            "png" => Some(Format::Png),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+}
+
+// A palette needs a background and a foreground, anything less gets the default
+pub(crate) fn usable(palette: &[Color]) -> &[Color] {
+    if palette.len() < 2 {
+        &DEFAULT_PALETTE
+    } else {
+        palette
+    }
+}
+
+// A two colour palette shows every lit plane in the foreground colour
+pub fn color(palette: &[Color], index: u8) -> Color {
+    let palette = usable(palette);
+    palette[(index as usize).min(palette.len() - 1)]
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// Plain PBM, any lit plane is black
+pub fn pbm(cpu: &CPU) -> String {
+    let mut out = format!("P1\n{} {}\n", cpu.width, cpu.height);
+    for row in cpu.vram.chunks_exact(cpu.width) {
+        let bits: Vec<&str> = row
+            .iter()
+            .map(|v| if *v != 0 { "1" } else { "0" })
+            .collect();
+        out.push_str(&bits.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+// An indexed PNG, each pixel scaled up to a `scale` x `scale` square
+pub fn png(cpu: &CPU, palette: &[Color], scale: u32) -> Vec<u8> {
+    let palette = usable(palette);
+    let scale = scale.max(1) as usize;
+    let (width, height) = (cpu.width * scale, cpu.height * scale);
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in cpu.vram.chunks_exact(cpu.width) {
+        let line: Vec<u8> = row
+            .iter()
+            .flat_map(|v| std::iter::repeat_n((*v as usize).min(palette.len() - 1) as u8, scale))
+            .collect();
+        for _ in 0..scale {
+            pixels.extend_from_slice(&line);
+        }
+    }
+
+    let mut out = vec![];
+    let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette.concat());
+
+    let mut writer = encoder.write_header().expect("PNG header");
+    writer.write_image_data(&pixels).expect("PNG data");
+    writer.finish().expect("PNG end");
+
+    out
+}
+
+// One rect per horizontal run of same coloured pixels
+pub fn svg(cpu: &CPU, palette: &[Color], scale: u32) -> String {
+    let scale = scale.max(1) as usize;
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}" shape-rendering="crispEdges">"#,
+        cpu.width * scale,
+        cpu.height * scale,
+        cpu.width,
+        cpu.height
+    );
+    let _ = writeln!(
+        out,
+        r#"<rect width="{}" height="{}" fill="{}"/>"#,
+        cpu.width,
+        cpu.height,
+        hex(color(palette, 0))
+    );
+
+    for (y, row) in cpu.vram.chunks_exact(cpu.width).enumerate() {
+        let mut x = 0;
+        while x < row.len() {
+            let start = x;
+            while x < row.len() && row[x] == row[start] {
+                x += 1;
+            }
+            if row[start] != 0 {
+                let _ = writeln!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="1" fill="{}"/>"#,
+                    start,
+                    y,
+                    x - start,
+                    hex(color(palette, row[start]))
+                );
+            }
+        }
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+// Write a screenshot, picking the format from the file extension
+pub fn save(cpu: &CPU, path: &Path, palette: &[Color], scale: u32) -> io::Result<()> {
+    let bytes = match Format::from_path(path) {
+        Some(Format::Pbm) => pbm(cpu).into_bytes(),
+        Some(Format::Png) => png(cpu, palette, scale),
+        Some(Format::Svg) => svg(cpu, palette, scale).into_bytes(),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown image format {}", path.display()),
+            ))
+        }
+    };
+    std::fs::write(path, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Instruction;
+
+    // Top left pixel on plane 1, next to it on both planes
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.vram[0] = 1;
+        cpu.vram[1] = 3;
+        cpu
+    }
+
+    #[test]
+    fn writes_pbm() {
+        let pbm = pbm(&cpu());
+        let lines: Vec<&str> = pbm.lines().collect();
+
+        assert_eq!(lines[0], "P1");
+        assert_eq!(lines[1], "64 32");
+        assert!(lines[2].starts_with("1 1 0 0"));
+        assert_eq!(lines.len(), 2 + 32);
+    }
+
+    #[test]
+    fn writes_scaled_indexed_png() {
+        let mut cpu = cpu();
+        cpu.execute(Instruction::HIRES);
+        cpu.vram[0] = 1;
+        cpu.vram[1] = 3;
+
+        let bytes = png(&cpu, &DEFAULT_PALETTE, 2);
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+
+        assert_eq!((info.width, info.height), (256, 128));
+        assert_eq!(&pixels[0..6], &[1, 1, 3, 3, 0, 0]);
+        assert_eq!(&pixels[256..262], &[1, 1, 3, 3, 0, 0]);
+        assert_eq!(
+            reader.info().palette.as_deref(),
+            Some(DEFAULT_PALETTE.concat().as_slice())
+        );
+    }
+
+    #[test]
+    fn two_colour_palettes_merge_planes() {
+        let palette = [[0, 0, 0], [0, 255, 0]];
+        let svg = svg(&cpu(), &palette, 4);
+
+        assert!(svg.contains(r#"width="256" height="128""#));
+        assert!(svg.contains(r##"<rect x="0" y="0" width="1" height="1" fill="#00ff00"/>"##));
+        assert!(svg.contains(r##"<rect x="1" y="0" width="1" height="1" fill="#00ff00"/>"##));
+    }
+
+    #[test]
+    fn short_palettes_fall_back_to_the_default() {
+        assert_eq!(svg(&cpu(), &[], 1), svg(&cpu(), &DEFAULT_PALETTE, 1));
+        assert_eq!(
+            png(&cpu(), &[[1, 2, 3]], 1),
+            png(&cpu(), &DEFAULT_PALETTE, 1)
+        );
+        assert_eq!(color(&[], 1), DEFAULT_PALETTE[1]);
+    }
+
+    #[test]
+    fn svg_merges_runs() {
+        let mut cpu = CPU::new();
+        cpu.vram[64..68].fill(2);
+
+        let svg = svg(&cpu, &DEFAULT_PALETTE, 1);
+        assert!(svg.contains(r##"<rect x="0" y="1" width="4" height="1" fill="#aaaaaa"/>"##));
+        assert_eq!(svg.matches("<rect").count(), 2);
+    }
+
+    #[test]
+    fn picks_the_format_from_the_extension() {
+        assert_eq!(Format::from_path(Path::new("a.PNG")), Some(Format::Png));
+        assert_eq!(Format::from_path(Path::new("a.svg")), Some(Format::Svg));
+        assert_eq!(Format::from_path(Path::new("a.pbm")), Some(Format::Pbm));
+        assert_eq!(Format::from_path(Path::new("a.gif")), None);
+    }
+}
@@ -0,0 +1,39 @@
+; IBM logo, draws six 8x15 sprites and loops forever
+
+TOP     EQU 8
+LEFT    EQU 12
+
+        CLS
+        LD I, letter_i
+        LD V0, LEFT
+        LD V1, TOP
+        DRW V0, V1, 15
+        ADD V0, 9
+        LD I, letter_b
+        DRW V0, V1, 15
+        LD I, letter_m_left
+        ADD V0, 8
+        DRW V0, V1, 15
+        ADD V0, 4
+        LD I, letter_m_middle
+        DRW V0, V1, 15
+        ADD V0, 8
+        LD I, letter_m_right
+        DRW V0, V1, 15
+        ADD V0, 8
+        LD I, letter_m_end
+        DRW V0, V1, 15
+loop:   JP loop
+
+letter_i:
+        DB #FF, #00, #FF, #00, #3C, #00, #3C, #00, #3C, #00, #3C, #00, #FF, #00, #FF
+letter_b:
+        DB #FF, #00, #FF, #00, #38, #00, #3F, #00, #3F, #00, #38, #00, #FF, #00, #FF
+letter_m_left:
+        DB #80, #00, #E0, #00, #E0, #00, #80, #00, #80, #00, #E0, #00, #E0, #00, #80
+letter_m_middle:
+        DB #F8, #00, #FC, #00, #3E, #00, #3F, #00, #3B, #00, #39, #00, #F8, #00, #F8
+letter_m_right:
+        DB #03, #00, #07, #00, #0F, #00, #BF, #00, #FB, #00, #F3, #00, #E3, #00, #43
+letter_m_end:
+        DB #E5, #05, #E2, #00, #85, #07, #81, #01, #80, #02, #80, #07, #E5, #05, #E7
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use super::instruction::Instruction;
+
+// The VIP runs a 1.7609 MHz crystal with 8 clocks per machine cycle, 60 frames a second
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+// Display DMA steals one machine cycle for each of the 128 lines x 8 bytes it shows
+pub const VIP_DMA_CYCLES: u32 = 1024;
+
+// Fetching and decoding an instruction in the interpreter's main loop
+const VIP_FETCH: u32 = 40;
+
+// What every instruction costs with `Timing::Fixed`
+pub const FIXED_CYCLES: u32 = 8;
+// The `clock_speed` that runs one instruction a frame with `Timing::Fixed`
+pub const CLOCK_SPEED_PER_IPF: u32 = 6000 * FIXED_CYCLES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Timing {
+    // Every instruction costs the same, budgeted from `clock_speed`
+    #[default]
+    Fixed,
+    // Machine cycles spent by the original COSMAC VIP interpreter
+    Vip,
+}
+
+impl Timing {
+    pub fn frame_budget(&self, clock_speed: u32) -> u32 {
+        match self {
+            Timing::Fixed => clock_speed / 6000,
+            Timing::Vip => VIP_CYCLES_PER_FRAME - VIP_DMA_CYCLES,
+        }
+    }
+
+    // `vx` are the registers before the instruction ran, `skipped` whether it skipped
+    pub fn cycles(&self, inst: Instruction, vx: &[u8; 16], skipped: bool) -> u32 {
+        match self {
+            Timing::Fixed => FIXED_CYCLES,
+            Timing::Vip => VIP_FETCH + vip_cycles(inst, vx, skipped),
+        }
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Timing, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "fixed" => Ok(Timing::Fixed),
+            "vip" => Ok(Timing::Vip),
+            _ => Err(format!("Unknown timing {}, expected fixed or vip", s)),
+        }
+    }
+}
+
+fn vip_cycles(inst: Instruction, vx: &[u8; 16], skipped: bool) -> u32 {
+    let skip = if skipped { 4 } else { 0 };
+
+    match inst {
+        // Clearing zeroes the whole 256 byte display page
+        Instruction::CLS => 24 + 3054,
+        Instruction::RET => 10,
+        Instruction::JP_addr(_) => 12,
+        Instruction::CALL_addr(_) => 26,
+        Instruction::SE_Vx_kk(_, _) | Instruction::SNE_Vx_kk(_, _) => 10 + skip,
+        Instruction::SE_Vx_Vy(_, _) | Instruction::SNE_Vx_Vy(_, _) => 14 + skip,
+        Instruction::SKP_Vx(_) | Instruction::SKNP_Vx(_) => 14 + skip,
+        Instruction::LD_Vx_kk(_, _) => 6,
+        Instruction::ADD_Vx_kk(_, _) => 10,
+        Instruction::LD_Vx_Vy(_, _)
+        | Instruction::OR_Vx_Vy(_, _)
+        | Instruction::AND_Vx_Vy(_, _)
+        | Instruction::XOR_Vx_Vy(_, _)
+        | Instruction::ADD_Vx_Vy(_, _)
+        | Instruction::SUB_Vx_Vy(_, _)
+        | Instruction::SHR_Vx_Vy(_, _)
+        | Instruction::SUBN_Vx_Vy(_, _)
+        | Instruction::SHL_Vx_Vy(_, _) => 44,
+        Instruction::LD_I_addr(_) => 12,
+        Instruction::JP_Vx_addr(_, addr) => {
+            // Crossing into the next page costs an extra carry
+            let target = addr + vx[0] as u16;
+            if target >> 8 != addr >> 8 {
+                24
+            } else {
+                22
+            }
+        }
+        Instruction::RND_Vx_kk(_, _) => 36,
+        Instruction::LD_Vx_DT(_) | Instruction::LD_DT_Vx(_) | Instruction::LD_ST_Vx(_) => 10,
+        Instruction::LD_Vx_K(_) => 10,
+        Instruction::ADD_I_Vx(_) => 16,
+        Instruction::LD_F_Vx(_) => 16,
+        Instruction::LD_B_Vx(x) => {
+            // Each digit is found by repeated subtraction
+            let v = vx[x as usize] as u32;
+            80 + 16 * (v / 100 + (v / 10) % 10 + v % 10)
+        }
+        Instruction::LD_iI_Vx(x) | Instruction::LD_Vx_iI(x) => 14 + 14 * (x as u32 + 1),
+        Instruction::DRW_Vx_Vy_n(x, _, n) => {
+            // Sprites not on a byte boundary are shifted into two bytes per row
+            let row = if vx[x as usize].is_multiple_of(8) {
+                34
+            } else {
+                68
+            };
+            26 + row * n as u32
+        }
+        // Not part of the VIP interpreter, cost them like a simple instruction
+        _ => 12,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_timing_is_flat() {
+        let vx = [0; 16];
+        assert_eq!(Timing::Fixed.cycles(Instruction::CLS, &vx, false), 8);
+        assert_eq!(
+            Timing::Fixed.cycles(Instruction::DRW_Vx_Vy_n(0, 0, 15), &vx, false),
+            8
+        );
+        assert_eq!(Timing::Fixed.frame_budget(1_000_000), 166);
+    }
+
+    #[test]
+    fn vip_timing_depends_on_the_instruction() {
+        let vx = [0; 16];
+        let vip = |inst, skipped| Timing::Vip.cycles(inst, &vx, skipped);
+
+        assert_eq!(vip(Instruction::LD_Vx_kk(0, 1), false), 46);
+        assert_eq!(vip(Instruction::ADD_Vx_Vy(0, 1), false), 84);
+        assert_eq!(vip(Instruction::SE_Vx_kk(0, 0), false), 50);
+        assert_eq!(vip(Instruction::SE_Vx_kk(0, 0), true), 54);
+        assert_eq!(vip(Instruction::LD_iI_Vx(3), false), 110);
+        assert!(vip(Instruction::CLS, false) > vip(Instruction::LD_Vx_kk(0, 1), false) * 50);
+    }
+
+    #[test]
+    fn vip_sprite_cost_depends_on_height_and_alignment() {
+        let mut vx = [0; 16];
+        let drw =
+            |vx: &[u8; 16], n| Timing::Vip.cycles(Instruction::DRW_Vx_Vy_n(0, 1, n), vx, false);
+
+        let aligned = drw(&vx, 5);
+        assert!(drw(&vx, 15) > aligned);
+
+        vx[0] = 3;
+        assert!(drw(&vx, 5) > aligned);
+
+        vx[0] = 16;
+        assert_eq!(drw(&vx, 5), aligned);
+    }
+}